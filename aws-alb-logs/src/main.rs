@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use clap::{Parser as ClapParser, ValueEnum};
+use color_eyre::eyre::{eyre, Result};
+
+use aws_cli_common::AwsOptions;
+use aws_logs_utils::log_types::RequestLogLine;
+use aws_logs_utils::output::OutputFormat;
+use aws_logs_utils::source::Source;
+use aws_logs_utils::{Parser, IGNORED_ELB_ERRORS, INTERESTING_ERRORS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Requests answered with a 5xx by the load balancer
+    FiveXx,
+    /// Requests where the target failed or could not be reached
+    TargetErrors,
+}
+
+#[derive(Debug, ClapParser)]
+pub struct Options {
+    pub region: String,
+    pub bucket: String,
+    pub prefix: String,
+    /// Extra filter, combined with the presets using &&
+    pub filter_query: Option<String>,
+    #[arg(short = 'p', long, value_enum)]
+    pub preset: Vec<Preset>,
+    /// Only show requests where the target took longer than this many seconds
+    #[arg(short = 's', long)]
+    pub slower_than: Option<f64>,
+    /// How to print matched lines: json, csv, table or debug
+    #[arg(short = 'o', long, default_value = "debug")]
+    pub output: OutputFormat,
+    /// How many log files are downloaded and filtered at the same time
+    #[arg(short = 'c', long, default_value_t = 4)]
+    pub concurrency: usize,
+    /// Process the biggest files first instead of following the listing order
+    #[arg(long)]
+    pub largest_first: bool,
+    #[command(flatten)]
+    pub aws: AwsOptions,
+}
+
+aws_cli_common::options!(Options);
+
+/// Convert a set of status codes to a wirefilter list, collapsing sequences into ranges.
+fn to_filter_list(codes: &HashSet<u16>) -> String {
+    let mut codes: Vec<_> = codes.iter().copied().collect();
+    codes.sort_unstable();
+
+    let mut ranges: Vec<(u16, u16)> = vec![];
+    for code in codes {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == code => *end = code,
+            _ => ranges.push((code, code)),
+        }
+    }
+
+    let ranges: Vec<_> = ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}..{}", start, end)
+            }
+        })
+        .collect();
+    format!("{{{}}}", ranges.join(" "))
+}
+
+fn preset_query(preset: Preset) -> String {
+    match preset {
+        Preset::FiveXx => "elb_status_code >= 500 && elb_status_code <= 599".to_string(),
+        Preset::TargetErrors => {
+            let interesting = to_filter_list(&INTERESTING_ERRORS);
+            format!(
+                "(elb_status_code in {} || target_status_code in {}) && not (elb_status_code in {})",
+                interesting,
+                interesting,
+                to_filter_list(&IGNORED_ELB_ERRORS)
+            )
+        }
+    }
+}
+
+fn build_filter_query(options: &Options) -> Result<String> {
+    let mut parts: Vec<String> = options.preset.iter().copied().map(preset_query).collect();
+    if let Some(seconds) = options.slower_than {
+        parts.push(format!(
+            "target_processing_time > {}",
+            (seconds * 1000.0).round() as i32
+        ));
+    }
+    if let Some(ref filter_query) = options.filter_query {
+        parts.push(filter_query.clone());
+    }
+
+    match parts.len() {
+        0 => Err(eyre!(
+            "At least one of filter_query, --preset or --slower-than has to be defined"
+        )),
+        1 => Ok(parts.remove(0)),
+        _ => Ok(parts
+            .iter()
+            .map(|part| format!("({})", part))
+            .collect::<Vec<_>>()
+            .join(" && ")),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    aws_cli_common::init()?;
+    let filter_query = build_filter_query(&OPTIONS)?;
+    let aws = OPTIONS.aws.clone().with_default_region(&OPTIONS.region);
+    let s3_client = aws.client(aws_s3_utils::client).await;
+    let source =
+        Source::s3(&s3_client, &OPTIONS.bucket, &OPTIONS.prefix).with_retry(aws.retry_policy());
+    let parser = Parser::<RequestLogLine>::from_source(source, &filter_query)?
+        .with_output_format(OPTIONS.output)
+        .with_concurrency(OPTIONS.concurrency)
+        .with_largest_first(OPTIONS.largest_first);
+    parser.parse_logs()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use clap::ValueEnum;
+
+    use aws_logs_utils::log_types::ALB_SCHEME;
+    use aws_logs_utils::output::OutputFormat;
+
+    use super::{build_filter_query, preset_query, to_filter_list, Options, Preset};
+
+    fn options() -> Options {
+        Options {
+            region: "eu-central-1".to_string(),
+            bucket: "bucket".to_string(),
+            prefix: "prefix".to_string(),
+            filter_query: None,
+            preset: vec![],
+            slower_than: None,
+            output: OutputFormat::Debug,
+            concurrency: 1,
+            largest_first: false,
+            aws: Default::default(),
+        }
+    }
+
+    fn assert_valid(query: &str) {
+        if let Err(e) = ALB_SCHEME.parse(query) {
+            panic!("{} is not a valid filter: {}", query, e);
+        }
+    }
+
+    #[test]
+    fn test_to_filter_list() {
+        let codes: HashSet<u16> = vec![200, 501, 502, 503, 504, 404].into_iter().collect();
+        assert_eq!(to_filter_list(&codes), "{200 404 501..504}");
+    }
+
+    #[test]
+    fn test_build_filter_query() {
+        assert!(build_filter_query(&options()).is_err());
+
+        let only_query = Options {
+            filter_query: Some(r#"method == "POST""#.to_string()),
+            ..options()
+        };
+        assert_eq!(
+            build_filter_query(&only_query).unwrap(),
+            r#"method == "POST""#
+        );
+
+        let combined = Options {
+            preset: vec![Preset::TargetErrors],
+            slower_than: Some(2.5),
+            ..options()
+        };
+        assert_eq!(
+            build_filter_query(&combined).unwrap(),
+            "((elb_status_code in {501..504} || target_status_code in {501..504}) && not (elb_status_code in {300..460})) && (target_processing_time > 2500)"
+        );
+        assert_valid(&build_filter_query(&combined).unwrap());
+    }
+
+    #[test]
+    fn test_presets_are_valid_filters() {
+        for &preset in Preset::value_variants() {
+            assert_valid(&preset_query(preset));
+        }
+
+        let everything = Options {
+            filter_query: Some(r#"method == "POST""#.to_string()),
+            preset: Preset::value_variants().to_vec(),
+            slower_than: Some(0.25),
+            ..options()
+        };
+        assert_valid(&build_filter_query(&everything).unwrap());
+    }
+}
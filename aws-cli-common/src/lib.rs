@@ -0,0 +1,209 @@
+use aws_config::retry::RetryConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::provider::SharedCredentialsProvider;
+use clap::Args;
+use color_eyre::eyre::{eyre, Result};
+
+use crate::retry::RetryPolicy;
+
+pub mod retry;
+
+#[doc(hidden)]
+pub use clap;
+#[doc(hidden)]
+pub use lazy_static;
+
+/// Session name shown in CloudTrail for requests made with `--role-arn`.
+const ROLE_SESSION_NAME: &str = "cli-utils";
+
+/// Declare the `OPTIONS` of a command, parsed from the command line the first time they are used.
+#[macro_export]
+macro_rules! options {
+    ($options:ty) => {
+        $crate::lazy_static::lazy_static! {
+            pub(crate) static ref OPTIONS: $options = <$options as $crate::clap::Parser>::parse();
+        }
+    };
+}
+
+/// Install the colored error reports and the logger, configured with RUST_LOG.
+pub fn init() -> Result<()> {
+    color_eyre::install()?;
+    env_logger::try_init().map_err(|e| eyre!("failed to initialize logging: {}", e))
+}
+
+/// Size in bytes with a binary unit, e.g. `2.00 KiB`.
+pub fn format_size(size: u64) -> String {
+    human_format::Formatter::new()
+        .with_scales(human_format::Scales::Binary())
+        .with_units("B")
+        .format(size as f64)
+}
+
+/// Options every AWS command accepts to choose the account, region and endpoint it talks to.
+#[derive(Debug, Clone, PartialEq, Args)]
+// Without this the doc comment above becomes the description of the commands flattening it
+#[command(about = None, long_about = None)]
+pub struct AwsOptions {
+    /// Region to send requests to, by default AWS_DEFAULT_REGION or AWS_REGION
+    #[arg(id = "aws_region", short = 'r', long = "region", value_name = "REGION")]
+    pub region: Option<String>,
+    /// Send requests to this endpoint instead of AWS, e.g. MinIO, LocalStack or a VPC endpoint
+    #[arg(long, env = "AWS_ENDPOINT_URL", value_name = "URL")]
+    pub endpoint_url: Option<String>,
+    /// Profile from ~/.aws/config or ~/.aws/credentials to use, by default AWS_PROFILE or "default"
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Assume this role and make every request with its credentials
+    #[arg(long, value_name = "ARN")]
+    pub role_arn: Option<String>,
+    /// Attempts per request before giving up, throttling, server errors and timeouts are retried
+    #[arg(long, default_value_t = RetryPolicy::default().max_attempts, value_name = "ATTEMPTS")]
+    pub max_attempts: u32,
+}
+
+impl Default for AwsOptions {
+    fn default() -> Self {
+        Self {
+            region: None,
+            endpoint_url: None,
+            profile: None,
+            role_arn: None,
+            max_attempts: RetryPolicy::default().max_attempts,
+        }
+    }
+}
+
+impl AwsOptions {
+    /// Use `region` unless --region was given, for commands which take it as a positional
+    /// argument.
+    pub fn with_default_region(mut self, region: &str) -> Self {
+        self.region.get_or_insert_with(|| region.to_owned());
+        self
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::default().with_max_attempts(self.max_attempts)
+    }
+
+    /// Configuration for the SDK clients. Credentials come from the usual chain, environment,
+    /// profile, SSO, container or instance metadata, traded for the ones of --role-arn when it is
+    /// set.
+    ///
+    /// The SDK does not retry on its own, requests are retried with `retry_policy` instead so a
+    /// failure is not attempted `max_attempts` times over.
+    pub async fn config(&self) -> SdkConfig {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(region) = &self.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        if let Some(profile) = &self.profile {
+            loader = loader.profile_name(profile);
+        }
+        let base = loader.load().await;
+
+        let mut config = base.clone().into_builder();
+        if let Some(role_arn) = &self.role_arn {
+            // STS is always reached on AWS itself, --endpoint-url only applies to the service
+            let provider = AssumeRoleProvider::builder(role_arn)
+                .session_name(ROLE_SESSION_NAME)
+                .configure(&base)
+                .build()
+                .await;
+            config.set_credentials_provider(Some(SharedCredentialsProvider::new(provider)));
+        }
+        if let Some(endpoint) = &self.endpoint_url {
+            config.set_endpoint_url(Some(endpoint.clone()));
+        }
+        config.retry_config(RetryConfig::disabled()).build()
+    }
+
+    /// Build a service client, e.g. `options.client(aws_sdk_ssm::Client::new).await`.
+    pub async fn client<C>(&self, new_client: impl FnOnce(&SdkConfig) -> C) -> C {
+        new_client(&self.config().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::Region;
+    use clap::{CommandFactory, Parser};
+
+    use super::AwsOptions;
+
+    #[derive(Debug, Parser)]
+    struct Command {
+        region: Option<String>,
+        #[command(flatten)]
+        aws: AwsOptions,
+    }
+
+    fn parse(arguments: &[&str]) -> Command {
+        Command::parse_from(std::iter::once("command").chain(arguments.iter().copied()))
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let command = parse(&[
+            "us-east-1",
+            "--region",
+            "eu-central-1",
+            "--endpoint-url",
+            "http://localhost:4566",
+            "--profile",
+            "staging",
+        ]);
+        assert_eq!(command.region.as_deref(), Some("us-east-1"));
+        assert_eq!(command.aws.region.as_deref(), Some("eu-central-1"));
+        assert_eq!(
+            command.aws.endpoint_url.as_deref(),
+            Some("http://localhost:4566")
+        );
+        assert_eq!(command.aws.profile.as_deref(), Some("staging"));
+        assert_eq!(command.aws.max_attempts, 5);
+    }
+
+    #[test]
+    fn test_no_description() {
+        assert_eq!(Command::command().get_about(), None);
+        assert_eq!(Command::command().get_long_about(), None);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let command = parse(&["--max-attempts", "0"]);
+        assert_eq!(command.aws.retry_policy().max_attempts, 1);
+        assert_eq!(AwsOptions::default().retry_policy().max_attempts, 5);
+    }
+
+    #[test]
+    fn test_default_region() {
+        let options = AwsOptions::default().with_default_region("us-west-2");
+        assert_eq!(options.region.as_deref(), Some("us-west-2"));
+
+        let options = AwsOptions {
+            region: Some("eu-west-1".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.with_default_region("us-west-2").region.as_deref(),
+            Some("eu-west-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_config() {
+        let options = AwsOptions {
+            region: Some("eu-central-1".to_owned()),
+            endpoint_url: Some("http://localhost:4566".to_owned()),
+            role_arn: Some("arn:aws:iam::123456789012:role/read-logs".to_owned()),
+            ..Default::default()
+        };
+        let config = options.config().await;
+        assert_eq!(config.region(), Some(&Region::new("eu-central-1")));
+        assert_eq!(config.endpoint_url(), Some("http://localhost:4566"));
+        assert!(config.credentials_provider().is_some());
+        assert_eq!(config.retry_config().unwrap().max_attempts(), 1);
+    }
+}
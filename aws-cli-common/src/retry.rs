@@ -0,0 +1,278 @@
+use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
+
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::SdkError;
+use aws_smithy_types::error::display::DisplayErrorContext;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
+use log::warn;
+
+/// Why a request failed, which decides if and how soon it is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The service asked to slow down, retried with a longer backoff
+    Throttling,
+    /// 5xx responses
+    Server,
+    /// Timeouts and connections which could not be made or broke halfway
+    Connection,
+    /// Anything else, e.g. a missing bucket or denied access, which is not worth retrying
+    Permanent,
+}
+
+impl ErrorKind {
+    pub fn is_retryable(self) -> bool {
+        self != ErrorKind::Permanent
+    }
+}
+
+/// Errors which can tell whether the request that failed is worth retrying.
+pub trait Retryable {
+    fn kind(&self) -> ErrorKind;
+}
+
+impl<E: ProvideErrorMetadata> Retryable for SdkError<E, HttpResponse> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            SdkError::TimeoutError(_) | SdkError::ResponseError(_) => ErrorKind::Connection,
+            SdkError::DispatchFailure(failure) if !failure.is_user() => ErrorKind::Connection,
+            SdkError::ServiceError(error) => classify_response(
+                error.raw().status().as_u16(),
+                error.err().code().unwrap_or_default(),
+            ),
+            _ => ErrorKind::Permanent,
+        }
+    }
+}
+
+/// Throttling and most server errors have no typed variant in the SDK, so they are told apart by
+/// status and error code.
+fn classify_response(status: u16, code: &str) -> ErrorKind {
+    const THROTTLING: &[&str] = &[
+        "SlowDown",
+        "Throttling",
+        "ThrottlingException",
+        "RequestLimitExceeded",
+        "TooManyRequestsException",
+    ];
+    if status == 429 || THROTTLING.contains(&code) {
+        ErrorKind::Throttling
+    } else if status == 408 || code == "RequestTimeout" {
+        ErrorKind::Connection
+    } else if status >= 500 {
+        ErrorKind::Server
+    } else {
+        ErrorKind::Permanent
+    }
+}
+
+/// How often and how long to wait before retrying a failed request. The delay doubles on every
+/// attempt up to `max_delay`, and a random part of it is left out so concurrent requests do not
+/// retry all at the same time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Including the first one, 1 disables retries
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Used instead of `base_delay` when S3 is throttling requests
+    pub throttling_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(200),
+            throttling_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(20),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Longest time to wait after `attempt` failed with `kind`, or `None` if it should not be
+    /// retried.
+    pub fn max_delay(&self, kind: ErrorKind, attempt: u32) -> Option<Duration> {
+        let base = match kind {
+            _ if attempt >= self.max_attempts => return None,
+            ErrorKind::Permanent => return None,
+            ErrorKind::Throttling => self.throttling_delay,
+            ErrorKind::Server | ErrorKind::Connection => self.base_delay,
+        };
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        Some(base.saturating_mul(factor).min(self.max_delay))
+    }
+
+    /// Like `max_delay` with jitter, waits somewhere between half of it and all of it.
+    pub fn delay(&self, kind: ErrorKind, attempt: u32) -> Option<Duration> {
+        self.max_delay(kind, attempt)
+            .map(|delay| delay.mul_f64(0.5 + rand::random::<f64>() / 2.0))
+    }
+
+    /// Run `operation` until it succeeds, fails with an error which is not retryable or runs out
+    /// of attempts. `what` is only used for logging.
+    pub async fn retry<T, E, F, Fut>(&self, what: &str, mut operation: F) -> Result<T, E>
+    where
+        E: Retryable + Error,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            let delay = match self.delay(error.kind(), attempt) {
+                Some(delay) => delay,
+                None => return Err(error),
+            };
+            warn!(
+                "{} failed on attempt {}/{}, retrying in {:?}: {}",
+                what,
+                attempt,
+                self.max_attempts,
+                delay,
+                DisplayErrorContext(&error)
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::convert::TryInto;
+    use std::time::Duration;
+
+    use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+    use aws_smithy_runtime_api::client::result::SdkError;
+    use aws_smithy_types::body::SdkBody;
+    use aws_smithy_types::error::ErrorMetadata;
+
+    use super::{classify_response, ErrorKind, RetryPolicy, Retryable};
+
+    type Error = SdkError<ErrorMetadata, HttpResponse>;
+
+    fn timeout_error() -> Error {
+        SdkError::timeout_error("timed out")
+    }
+
+    fn service_error(status: u16, code: &str) -> Error {
+        let response = HttpResponse::new(status.try_into().unwrap(), SdkBody::empty());
+        SdkError::service_error(ErrorMetadata::builder().code(code).build(), response)
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        assert_eq!(classify_response(503, "SlowDown"), ErrorKind::Throttling);
+        assert_eq!(
+            classify_response(400, "ThrottlingException"),
+            ErrorKind::Throttling
+        );
+        assert_eq!(classify_response(429, ""), ErrorKind::Throttling);
+        assert_eq!(classify_response(500, "InternalError"), ErrorKind::Server);
+        assert_eq!(
+            classify_response(400, "RequestTimeout"),
+            ErrorKind::Connection
+        );
+        assert_eq!(classify_response(403, "AccessDenied"), ErrorKind::Permanent);
+
+        assert_eq!(timeout_error().kind(), ErrorKind::Connection);
+        assert_eq!(service_error(503, "SlowDown").kind(), ErrorKind::Throttling);
+        assert_eq!(
+            service_error(400, "ParameterAlreadyExists").kind(),
+            ErrorKind::Permanent
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| policy.max_delay(ErrorKind::Server, attempt))
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(800)),
+                Some(Duration::from_millis(1600)),
+                None
+            ]
+        );
+        assert_eq!(
+            policy.max_delay(ErrorKind::Throttling, 4),
+            Some(Duration::from_secs(8))
+        );
+        assert_eq!(policy.max_delay(ErrorKind::Permanent, 1), None);
+
+        let policy = policy.with_max_attempts(100);
+        assert_eq!(
+            policy.max_delay(ErrorKind::Throttling, 50),
+            Some(Duration::from_secs(20))
+        );
+        let delay = policy.delay(ErrorKind::Server, 1).unwrap();
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        let calls = Cell::new(0);
+        let result = policy
+            .retry("listing", || {
+                calls.set(calls.get() + 1);
+                let result = if calls.get() < 3 {
+                    Err(timeout_error())
+                } else {
+                    Ok(calls.get())
+                };
+                async move { result }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+
+        calls.set(0);
+        let result: Result<(), _> = policy
+            .retry("listing", || {
+                calls.set(calls.get() + 1);
+                async { Err(timeout_error()) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+
+        calls.set(0);
+        let result: Result<(), _> = policy
+            .retry("listing", || {
+                calls.set(calls.get() + 1);
+                async { Err(service_error(404, "NoSuchBucket")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+}
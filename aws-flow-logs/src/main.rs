@@ -1,34 +1,295 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Utc;
 use clap::Parser as ClapParser;
-use color_eyre::eyre::{Result, WrapErr};
-use rusoto_core::Region;
-use rusoto_s3::S3Client;
+use color_eyre::eyre::{eyre, Result};
 
+use aws_cli_common::AwsOptions;
+use aws_logs_utils::aggregate::{Aggregate, GroupBy};
 use aws_logs_utils::log_types::FlowLogLine;
+use aws_logs_utils::output::OutputFormat;
+use aws_logs_utils::queries::QueryConfig;
+use aws_logs_utils::source::Source;
+use aws_logs_utils::time_range::{TimeRange, TimeSpec};
 use aws_logs_utils::Parser;
 
 #[derive(Debug, ClapParser)]
 pub struct Options {
-    pub region: String,
-    pub bucket: String,
-    pub prefix: String,
-    pub filter_query: String,
+    /// <REGION> <BUCKET> <PREFIX> <FILTER_QUERY>, or only <FILTER_QUERY> when --input is used.
+    /// <FILTER_QUERY> is left out when using --query
+    #[arg(
+        num_args = 0..=4,
+        value_names = ["REGION", "BUCKET", "PREFIX", "FILTER_QUERY"]
+    )]
+    pub arguments: Vec<String>,
+    /// TOML file with named queries and lists which can be used as $name in filters
+    #[arg(long, env = "AWS_FLOW_LOGS_CONFIG", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Use a query saved in --config instead of <FILTER_QUERY>
+    #[arg(short = 'q', long, value_name = "NAME")]
+    pub query: Option<String>,
+    /// Read logs from a local file, directory, glob or - for stdin instead of S3
+    #[arg(short = 'i', long)]
+    pub input: Option<String>,
+    /// How to print matched lines: json, csv, table or debug
+    #[arg(short = 'o', long, default_value = "debug")]
+    pub output: OutputFormat,
+    /// How many log files are downloaded and filtered at the same time
+    #[arg(short = 'c', long, default_value_t = 4)]
+    pub concurrency: usize,
+    /// Process the biggest files first instead of following the listing order
+    #[arg(long)]
+    pub largest_first: bool,
+    /// Only scan records after this time, e.g. 2024-03-01T10:00:00Z, 2024-03-01 or 2h for two
+    /// hours ago. PREFIX is expanded into one prefix per day, so it should stop at the region
+    #[arg(long)]
+    pub since: Option<TimeSpec>,
+    /// Only scan records before this time, same format as --since
+    #[arg(long)]
+    pub until: Option<TimeSpec>,
+    /// Instead of printing matches, sum their packets and bytes by these comma separated fields:
+    /// srcaddr, dstaddr, dstport, protocol and action
+    #[arg(short = 'g', long, alias = "aggregate", value_delimiter = ',')]
+    pub group_by: Vec<GroupBy>,
+    /// How many groups to print with --group-by, the ones with the most bytes first
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+    /// Stop at the first line that cannot be parsed instead of skipping it
+    #[arg(long)]
+    pub strict: bool,
+    /// Write the lines that could not be parsed to this file
+    #[arg(long, value_name = "FILE")]
+    pub rejected_lines: Option<PathBuf>,
+    /// Record the log files which were completely processed in this file, so an interrupted scan
+    /// can be continued with --resume. Groups are only printed at the end, so it cannot be used
+    /// with --group-by
+    #[arg(long, value_name = "FILE", conflicts_with = "group_by")]
+    pub checkpoint: Option<PathBuf>,
+    /// Skip the files already recorded in --checkpoint and append to --rejected-lines instead of
+    /// starting over
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+    /// Keep running and filter new log files as they arrive, like tail -f. Files already there
+    /// are skipped unless --since is used. PREFIX is listed as is on every poll instead of once
+    /// per day, so it should be as narrow as possible
+    #[arg(short = 'f', long, conflicts_with = "group_by")]
+    pub follow: bool,
+    /// Seconds to wait between two listings with --follow
+    #[arg(long, default_value_t = 60, value_name = "SECONDS")]
+    pub interval: u64,
+    #[command(flatten)]
+    pub aws: AwsOptions,
+}
+
+/// Where the logs should be read from, based on the positional arguments and --input.
+#[derive(Debug, PartialEq)]
+pub enum Location<'a> {
+    S3 {
+        region: &'a str,
+        bucket: &'a str,
+        prefix: &'a str,
+    },
+    Local(&'a str),
 }
 
-lazy_static::lazy_static! {
-    pub (crate) static ref OPTIONS: Options = Options::parse();
+impl Options {
+    /// Split the positional arguments into the location and the filter query, which is missing
+    /// when --query is used.
+    fn split_arguments(&self) -> (&[String], Option<&str>) {
+        match (&self.query, self.arguments.split_last()) {
+            (None, Some((filter_query, location))) => (location, Some(filter_query)),
+            _ => (&self.arguments, None),
+        }
+    }
+
+    /// The filter query with the macros from --config expanded.
+    pub fn filter_query(&self, config: &QueryConfig) -> Result<String> {
+        match (&self.query, self.split_arguments().1) {
+            (Some(name), _) => config.query(name),
+            (None, Some(filter_query)) => config.expand(filter_query),
+            (None, None) => Err(eyre!("Either <FILTER_QUERY> or --query is required")),
+        }
+    }
+
+    pub fn location(&self) -> Result<Location<'_>> {
+        match (&self.input, self.split_arguments().0) {
+            (Some(input), []) => Ok(Location::Local(input)),
+            (Some(_), _) => Err(eyre!("Only <FILTER_QUERY> is expected when using --input")),
+            (None, [region, bucket, prefix]) => Ok(Location::S3 {
+                region,
+                bucket,
+                prefix,
+            }),
+            (None, _) => Err(eyre!(
+                "<REGION> <BUCKET> <PREFIX> are required when not using --input"
+            )),
+        }
+    }
+
+    pub fn query_config(&self) -> Result<QueryConfig> {
+        match (&self.config, &self.query) {
+            (Some(path), _) => QueryConfig::load(path),
+            (None, Some(_)) => Err(eyre!("--query needs a --config file to read queries from")),
+            (None, None) => Ok(QueryConfig::default()),
+        }
+    }
+
+    pub fn time_range(&self) -> TimeRange {
+        let now = Utc::now();
+        TimeRange::new(
+            self.since.map(|since| since.resolve(now)),
+            self.until.map(|until| until.resolve(now)),
+        )
+    }
 }
 
+aws_cli_common::options!(Options);
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    color_eyre::install()?;
-    env_logger::try_init().wrap_err("Error initializing log")?;
-    let s3_client = S3Client::new(Region::default());
-    let parser = Parser::new(
-        &s3_client,
-        &OPTIONS.bucket,
-        &OPTIONS.prefix,
-        &OPTIONS.filter_query,
-    );
-    parser.parse_logs::<FlowLogLine>()?;
+    aws_cli_common::init()?;
+    let location = OPTIONS.location()?;
+    let time_range = OPTIONS.time_range();
+    // Only built for S3, local files can be read without any AWS configuration
+    let s3_client;
+    let source = match location {
+        Location::S3 {
+            region,
+            bucket,
+            prefix,
+        } => {
+            let aws = OPTIONS.aws.clone().with_default_region(region);
+            s3_client = aws.client(aws_s3_utils::client).await;
+            let source = if OPTIONS.follow {
+                Source::s3(&s3_client, bucket, prefix)
+            } else {
+                Source::s3_prefixes(&s3_client, bucket, time_range.daily_prefixes(prefix))
+            };
+            source.with_retry(aws.retry_policy())
+        }
+        Location::Local(input) => Source::local(input),
+    };
+    let filter_query = OPTIONS.filter_query(&OPTIONS.query_config()?)?;
+    let parser = Parser::<FlowLogLine>::from_source(source, &filter_query)?
+        .with_output_format(OPTIONS.output)
+        .with_concurrency(OPTIONS.concurrency)
+        .with_largest_first(OPTIONS.largest_first)
+        .with_time_range(time_range)
+        .with_strict(OPTIONS.strict)
+        .with_rejected_lines(OPTIONS.rejected_lines.clone())
+        .with_checkpoint(OPTIONS.checkpoint.clone())
+        .with_resume(OPTIONS.resume);
+    if OPTIONS.follow {
+        if OPTIONS.output == OutputFormat::Table {
+            return Err(eyre!("--output table cannot be used with --follow"));
+        }
+        let mut output = OPTIONS.output.writer::<FlowLogLine, _>(io::stdout());
+        let interval = Duration::from_secs(OPTIONS.interval);
+        return parser.follow(output.as_mut(), interval, OPTIONS.since.is_some());
+    }
+    let stats = if OPTIONS.group_by.is_empty() {
+        parser.parse_logs()?
+    } else {
+        let mut output = Aggregate::new(io::stdout(), OPTIONS.group_by.clone(), OPTIONS.top);
+        parser.parse_logs_with_output(&mut output)?
+    };
+    eprintln!("{}", stats);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use aws_logs_utils::output::OutputFormat;
+    use aws_logs_utils::queries::QueryConfig;
+
+    use super::{Location, Options};
+
+    fn options(arguments: &[&str], input: Option<&str>) -> Options {
+        Options {
+            arguments: arguments.iter().map(|a| a.to_string()).collect(),
+            config: None,
+            query: None,
+            input: input.map(str::to_string),
+            output: OutputFormat::Debug,
+            concurrency: 1,
+            largest_first: false,
+            since: None,
+            until: None,
+            group_by: vec![],
+            top: 20,
+            strict: false,
+            rejected_lines: None,
+            checkpoint: None,
+            resume: false,
+            follow: false,
+            interval: 60,
+            aws: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_location() {
+        let s3 = options(&["eu-central-1", "bucket", "prefix", "bytes > 0"], None);
+        assert_eq!(
+            s3.location().unwrap(),
+            Location::S3 {
+                region: "eu-central-1",
+                bucket: "bucket",
+                prefix: "prefix"
+            }
+        );
+        let config = QueryConfig::default();
+        assert_eq!(s3.filter_query(&config).unwrap(), "bytes > 0");
+
+        let local = options(&["bytes > 0"], Some("-"));
+        assert_eq!(local.location().unwrap(), Location::Local("-"));
+        assert_eq!(local.filter_query(&config).unwrap(), "bytes > 0");
+
+        assert!(options(&["bytes > 0"], None).location().is_err());
+        assert!(options(&["bucket", "bytes > 0"], Some("logs/"))
+            .location()
+            .is_err());
+    }
+
+    #[test]
+    fn test_saved_query() {
+        let config: QueryConfig = r#"
+[lists]
+internal = "10.0.0.0/8"
+
+[queries]
+rejected-internal = 'dstaddr in $internal && action == "REJECT"'
+"#
+        .parse()
+        .unwrap();
+
+        let mut saved = options(&["eu-central-1", "bucket", "prefix"], None);
+        saved.query = Some("rejected-internal".to_string());
+        assert_eq!(
+            saved.location().unwrap(),
+            Location::S3 {
+                region: "eu-central-1",
+                bucket: "bucket",
+                prefix: "prefix"
+            }
+        );
+        assert_eq!(
+            saved.filter_query(&config).unwrap(),
+            r#"dstaddr in {10.0.0.0/8} && action == "REJECT""#
+        );
+        assert!(saved.query_config().is_err());
+
+        let mut local = options(&[], Some("-"));
+        local.query = Some("rejected-internal".to_string());
+        assert_eq!(local.location().unwrap(), Location::Local("-"));
+
+        let inline = options(&["srcaddr in $internal"], Some("-"));
+        assert_eq!(
+            inline.filter_query(&config).unwrap(),
+            "srcaddr in {10.0.0.0/8}"
+        );
+        assert!(options(&[], Some("-")).filter_query(&config).is_err());
+    }
+}
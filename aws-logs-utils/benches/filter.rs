@@ -0,0 +1,127 @@
+use std::io::Write;
+
+use color_eyre::eyre::Result;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use aws_logs_utils::log_types::{FlowLogLine, Searchable, FLOW_SCHEME};
+use aws_logs_utils::output::{Match, Output};
+use aws_logs_utils::source::Source;
+use aws_logs_utils::{Parser, FILTER_BATCH_SIZE};
+
+const LINES: usize = 100_000;
+const QUERY: &str = r#"dstport in {22 3389} && action == "REJECT" && srcaddr in {10.0.0.0/8}"#;
+
+/// Version 2 flow log where every tenth record is a rejected ssh connection.
+fn synthetic_flow_log(lines: usize) -> String {
+    let mut log = "version account-id interface-id srcaddr dstaddr srcport dstport protocol packets bytes start end action log-status\n".to_owned();
+    for line in 0..lines {
+        let (dstport, action) = if line % 10 == 0 {
+            (22, "REJECT")
+        } else {
+            (443, "ACCEPT")
+        };
+        log.push_str(&format!(
+            "2 123456789010 eni-1235b8ca123456789 10.0.{}.{} 172.31.16.21 {} {} 6 20 4249 1418530010 1418530070 {} OK\n",
+            line / 256 % 256,
+            line % 256,
+            1024 + line % 60000,
+            dstport,
+            action
+        ));
+    }
+    log
+}
+
+fn records(log: &str) -> Vec<FlowLogLine> {
+    csv::ReaderBuilder::new()
+        .delimiter(b' ')
+        .from_reader(log.as_bytes())
+        .into_deserialize()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+struct Count(usize);
+
+impl Output<FlowLogLine> for Count {
+    fn write_match(&mut self, _matched: &Match<FlowLogLine>) -> Result<()> {
+        self.0 += 1;
+        Ok(())
+    }
+}
+
+fn filter(c: &mut Criterion) {
+    let records = records(&synthetic_flow_log(LINES));
+    let ast = FLOW_SCHEME.parse(QUERY).unwrap();
+    let mut group = c.benchmark_group("filter");
+    group.throughput(Throughput::Elements(records.len() as u64));
+
+    group.bench_function("compile per line", |b| {
+        b.iter(|| {
+            records
+                .iter()
+                .filter(|record| {
+                    let filter = ast.clone().compile();
+                    filter
+                        .execute(&record.execution_context().unwrap())
+                        .unwrap()
+                })
+                .count()
+        })
+    });
+    group.bench_function("compile per batch", |b| {
+        b.iter(|| {
+            records
+                .chunks(FILTER_BATCH_SIZE)
+                .map(|batch| {
+                    let filter = ast.clone().compile();
+                    batch
+                        .iter()
+                        .filter(|record| {
+                            filter
+                                .execute(&record.execution_context().unwrap())
+                                .unwrap()
+                        })
+                        .count()
+                })
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("compile once", |b| {
+        b.iter(|| {
+            let filter = ast.clone().compile();
+            records
+                .iter()
+                .filter(|record| {
+                    filter
+                        .execute(&record.execution_context().unwrap())
+                        .unwrap()
+                })
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn parser(c: &mut Criterion) {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(synthetic_flow_log(LINES).as_bytes())
+        .unwrap();
+    let path = file.path().to_string_lossy().into_owned();
+    let parser = Parser::<FlowLogLine>::from_source(Source::local(&path), QUERY).unwrap();
+
+    let mut group = c.benchmark_group("parser");
+    group.throughput(Throughput::Elements(LINES as u64));
+    group.sample_size(10);
+    group.bench_function("synthetic flow log", |b| {
+        b.iter(|| {
+            let mut output = Count(0);
+            parser.parse_logs_with_output(&mut output).unwrap();
+            assert_eq!(output.0, LINES / 10);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, filter, parser);
+criterion_main!(benches);
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::Write;
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::log_types::FlowLogLine;
+use crate::output::{write_table, Match, Output};
+
+/// Flow log fields matches can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Srcaddr,
+    Dstaddr,
+    Dstport,
+    Protocol,
+    Action,
+}
+
+impl GroupBy {
+    fn name(self) -> &'static str {
+        match self {
+            GroupBy::Srcaddr => "srcaddr",
+            GroupBy::Dstaddr => "dstaddr",
+            GroupBy::Dstport => "dstport",
+            GroupBy::Protocol => "protocol",
+            GroupBy::Action => "action",
+        }
+    }
+
+    fn value(self, line: &FlowLogLine) -> String {
+        fn or_absent<T: Display>(value: &Option<T>) -> String {
+            value
+                .as_ref()
+                .map_or_else(|| "-".to_owned(), ToString::to_string)
+        }
+
+        match self {
+            GroupBy::Srcaddr => or_absent(&line.srcaddr),
+            GroupBy::Dstaddr => or_absent(&line.dstaddr),
+            GroupBy::Dstport => or_absent(&line.dstport),
+            GroupBy::Protocol => match line.protocol_name() {
+                Some(name) => name.to_owned(),
+                None => or_absent(&line.protocol),
+            },
+            GroupBy::Action => or_absent(&line.action),
+        }
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        match data {
+            "srcaddr" => Ok(GroupBy::Srcaddr),
+            "dstaddr" => Ok(GroupBy::Dstaddr),
+            "dstport" => Ok(GroupBy::Dstport),
+            "protocol" => Ok(GroupBy::Protocol),
+            "action" => Ok(GroupBy::Action),
+            _ => Err(eyre!(
+                "Invalid field {:?}, expected one of srcaddr, dstaddr, dstport, protocol or action",
+                data
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Totals {
+    records: u64,
+    packets: u64,
+    bytes: u64,
+}
+
+/// Sums the records, packets and bytes of every match by the selected fields and prints the
+/// groups with the most bytes as a table once all files were processed.
+pub struct Aggregate<W> {
+    writer: W,
+    group_by: Vec<GroupBy>,
+    top: usize,
+    groups: HashMap<Vec<String>, Totals>,
+}
+
+impl<W: Write> Aggregate<W> {
+    pub fn new(writer: W, group_by: Vec<GroupBy>, top: usize) -> Self {
+        Self {
+            writer,
+            group_by,
+            top,
+            groups: HashMap::new(),
+        }
+    }
+}
+
+impl<W: Write> Output<FlowLogLine> for Aggregate<W> {
+    fn write_match(&mut self, matched: &Match<FlowLogLine>) -> Result<()> {
+        let line = &matched.record;
+        let key = self
+            .group_by
+            .iter()
+            .map(|field| field.value(line))
+            .collect();
+        let totals = self.groups.entry(key).or_default();
+        totals.records += 1;
+        totals.packets += line.packets.map_or(0, |packets| packets.max(0) as u64);
+        totals.bytes += line.bytes.map_or(0, |bytes| bytes.max(0) as u64);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let mut groups: Vec<_> = self.groups.drain().collect();
+        groups.sort_by(|(a_key, a), (b_key, b)| {
+            (b.bytes, b.records, a_key).cmp(&(a.bytes, a.records, b_key))
+        });
+
+        let mut headers: Vec<String> = self
+            .group_by
+            .iter()
+            .map(|field| field.name().to_owned())
+            .collect();
+        headers.extend(
+            ["records", "packets", "bytes"]
+                .iter()
+                .map(|h| h.to_string()),
+        );
+        let rows: Vec<Vec<String>> = groups
+            .into_iter()
+            .take(self.top)
+            .map(|(mut row, totals)| {
+                row.push(totals.records.to_string());
+                row.push(totals.packets.to_string());
+                row.push(totals.bytes.to_string());
+                row
+            })
+            .collect();
+
+        write_table(&mut self.writer, &headers, &rows)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_types::FlowLogLine;
+    use crate::output::{Match, Output};
+
+    use super::{Aggregate, GroupBy};
+
+    fn flow(srcaddr: &str, dstport: i32, bytes: i64) -> Match<FlowLogLine> {
+        Match {
+            key: "flow.log".to_owned(),
+            line_number: 1,
+            record: FlowLogLine {
+                srcaddr: Some(srcaddr.parse().unwrap()),
+                dstport: Some(dstport),
+                packets: Some(2),
+                bytes: Some(bytes),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        assert_eq!("dstport".parse::<GroupBy>().unwrap(), GroupBy::Dstport);
+        assert!("bytes".parse::<GroupBy>().is_err());
+    }
+
+    #[test]
+    fn test_top_talkers() {
+        let mut data = vec![];
+        {
+            let group_by = vec![GroupBy::Srcaddr, GroupBy::Dstport];
+            let mut output = Aggregate::new(&mut data, group_by, 2);
+            for matched in &[
+                flow("10.0.0.1", 443, 100),
+                flow("10.0.0.2", 22, 5000),
+                flow("10.0.0.1", 443, 200),
+                flow("10.0.0.3", 80, 10),
+            ] {
+                output.write_match(matched).unwrap();
+            }
+            output.finish().unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "srcaddr   dstport  records  packets  bytes
+10.0.0.2  22       1        2        5000
+10.0.0.1  443      2        4        300
+"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use color_eyre::eyre::{Result, WrapErr};
+
+/// Keys of the log files which were completely processed, so an interrupted scan can be resumed
+/// without going through them again.
+///
+/// Keys are appended as one json string per line once all matches of the file were written, the
+/// listing itself is done again when resuming. A line cut short by a crash is dropped.
+pub struct Checkpoint {
+    file: File,
+    done: HashSet<String>,
+}
+
+impl Checkpoint {
+    /// Start a new checkpoint at `path`, or when resuming keep the keys already recorded there and
+    /// append to them.
+    pub fn open(path: &Path, resume: bool) -> Result<Self> {
+        let error = || format!("failed to open checkpoint {}", path.display());
+        if !resume {
+            let file = File::create(path).wrap_err_with(error)?;
+            return Ok(Self {
+                file,
+                done: HashSet::new(),
+            });
+        }
+
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).wrap_err_with(error),
+        };
+        let complete = data.rfind('\n').map_or(0, |end| end + 1);
+        let mut done = HashSet::new();
+        for (index, line) in data[..complete].lines().enumerate() {
+            let key = serde_json::from_str(line).wrap_err_with(|| {
+                format!("{}:{}: invalid checkpoint entry", path.display(), index + 1)
+            })?;
+            done.insert(key);
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .wrap_err_with(error)?;
+        file.set_len(complete as u64).wrap_err_with(error)?;
+        Ok(Self { file, done })
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.done.contains(key)
+    }
+
+    /// How many files were already processed.
+    pub fn done(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn record(&mut self, key: &str) -> Result<()> {
+        let mut line = serde_json::to_string(key)?;
+        line.push('\n');
+        // A single write so an interrupted one only leaves a partial last line behind
+        self.file
+            .write_all(line.as_bytes())
+            .wrap_err("failed to write checkpoint")?;
+        self.done.insert(key.to_owned());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use super::Checkpoint;
+
+    #[test]
+    fn test_resume_checkpoint() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint");
+
+        let mut checkpoint = Checkpoint::open(&path, true).unwrap();
+        assert_eq!(checkpoint.done(), 0);
+        checkpoint.record("2024/03/01/a.log.gz").unwrap();
+        checkpoint.record("odd\nkey").unwrap();
+        drop(checkpoint);
+        // Interrupted while writing the third key
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"\"2024/03/01/c.lo").unwrap();
+        drop(file);
+
+        let mut checkpoint = Checkpoint::open(&path, true).unwrap();
+        assert!(checkpoint.contains("2024/03/01/a.log.gz"));
+        assert!(checkpoint.contains("odd\nkey"));
+        assert_eq!(checkpoint.done(), 2);
+        checkpoint.record("2024/03/01/c.log.gz").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "\"2024/03/01/a.log.gz\"\n\"odd\\nkey\"\n\"2024/03/01/c.log.gz\"\n"
+        );
+
+        let checkpoint = Checkpoint::open(&path, false).unwrap();
+        assert_eq!(checkpoint.done(), 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+}
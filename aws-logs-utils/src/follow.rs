@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::source::LogFile;
+
+/// Remembers the newest files processed so far, so polling the same source again only returns
+/// the files which appeared since.
+///
+/// S3 only keeps the modification time to the second, so the keys modified during that last
+/// second are kept too: a file which shows up later with the same time is still new. Files without
+/// a modification time are only compared by key.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HighWaterMark {
+    last_modified: Option<DateTime<Utc>>,
+    keys_at_mark: HashSet<String>,
+    keys_without_time: HashSet<String>,
+}
+
+impl HighWaterMark {
+    pub fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.last_modified
+    }
+
+    pub fn is_new(&self, file: &LogFile) -> bool {
+        match (file.last_modified, self.last_modified) {
+            (None, _) => !self.keys_without_time.contains(&file.key),
+            (Some(_), None) => true,
+            (Some(time), Some(mark)) => {
+                time > mark || (time == mark && !self.keys_at_mark.contains(&file.key))
+            }
+        }
+    }
+
+    /// Only keep the files which were not seen yet, in the order they were listed.
+    pub fn new_files(&self, mut files: Vec<LogFile>) -> Vec<LogFile> {
+        files.retain(|file| self.is_new(file));
+        files
+    }
+
+    /// Move the mark past `files`, once they were processed.
+    pub fn advance(&mut self, files: &[LogFile]) {
+        for file in files {
+            match file.last_modified {
+                None => {
+                    self.keys_without_time.insert(file.key.clone());
+                }
+                Some(time) if Some(time) > self.last_modified => {
+                    self.last_modified = Some(time);
+                    self.keys_at_mark.clear();
+                    self.keys_at_mark.insert(file.key.clone());
+                }
+                Some(time) if Some(time) == self.last_modified => {
+                    self.keys_at_mark.insert(file.key.clone());
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::source::LogFile;
+
+    use super::HighWaterMark;
+
+    fn file(key: &str, minute: Option<u32>) -> LogFile {
+        LogFile {
+            key: key.to_owned(),
+            size: Some(10),
+            last_modified: minute
+                .map(|minute| Utc.with_ymd_and_hms(2024, 3, 1, 10, minute, 0).unwrap()),
+        }
+    }
+
+    fn keys(files: Vec<LogFile>) -> Vec<String> {
+        files.into_iter().map(|file| file.key).collect()
+    }
+
+    #[test]
+    fn test_only_new_files() {
+        let mut mark = HighWaterMark::default();
+        let first = vec![file("b.log.gz", Some(5)), file("a.log.gz", Some(10))];
+        assert_eq!(
+            keys(mark.new_files(first.clone())),
+            vec!["b.log.gz", "a.log.gz"]
+        );
+        mark.advance(&first);
+        assert_eq!(
+            mark.last_modified(),
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 10, 0).unwrap())
+        );
+
+        let second = vec![
+            file("a.log.gz", Some(10)),
+            file("b.log.gz", Some(5)),
+            file("0.log.gz", Some(10)),
+            file("c.log.gz", Some(15)),
+        ];
+        assert_eq!(
+            keys(mark.new_files(second.clone())),
+            vec!["0.log.gz", "c.log.gz"]
+        );
+        mark.advance(&second);
+        assert!(mark.new_files(second).is_empty());
+    }
+
+    #[test]
+    fn test_files_without_time() {
+        let mut mark = HighWaterMark::default();
+        mark.advance(&[file("a.log", None)]);
+        assert!(!mark.is_new(&file("a.log", None)));
+        assert!(mark.is_new(&file("b.log", None)));
+        assert!(mark.is_new(&file("a.log", Some(0))));
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::Read;
+use std::result::Result as StdResult;
+
+use bytes::Bytes;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use color_eyre::Report;
+use csv::StringRecord;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::reader::RowIter;
+use parquet::record::{Field, Row};
+use serde::de::DeserializeOwned;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const PARQUET_MAGIC: [u8; 4] = *b"PAR1";
+
+/// A line which could not be turned into a record. `raw` is missing when the line could not even
+/// be read, e.g. because of a truncated gzip file.
+#[derive(Debug)]
+pub struct BadLine {
+    pub raw: Option<String>,
+    pub error: Report,
+}
+
+pub(crate) type LineResult<T> = StdResult<T, BadLine>;
+
+/// How a log file is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Space delimited lines
+    Text,
+    /// Space delimited lines compressed with gzip
+    Gzip,
+    Parquet,
+}
+
+impl LogFormat {
+    /// Detect the format from the key suffix, falling back to the first bytes of the file.
+    pub fn detect(key: &str, head: &[u8]) -> Self {
+        if key.ends_with(".parquet") || head.starts_with(&PARQUET_MAGIC) {
+            LogFormat::Parquet
+        } else if head.starts_with(&GZIP_MAGIC) {
+            LogFormat::Gzip
+        } else {
+            LogFormat::Text
+        }
+    }
+}
+
+/// Read space delimited lines, matching fields by the header line when there is one. Lines may
+/// have any number of fields without a header, load balancers add new ones at the end from time
+/// to time, but with a header every line needs one field per column.
+///
+/// Every line is bad when the header has columns which are not in `fields`, the file is most
+/// likely not a log of type `T` at all.
+pub(crate) fn read_text<'r, T>(
+    file: impl Read + 'r,
+    has_headers: bool,
+    fields: &'static [&'static str],
+) -> Result<impl Iterator<Item = LineResult<T>> + 'r>
+where
+    T: DeserializeOwned + 'r,
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b' ')
+        .has_headers(has_headers)
+        .flexible(true)
+        .from_reader(file);
+    let headers = if has_headers {
+        Some(reader.headers().wrap_err("failed to read headers")?.clone())
+    } else {
+        None
+    };
+
+    let unknown = headers
+        .as_ref()
+        .and_then(|headers| unknown_columns(headers, fields));
+
+    Ok(reader.into_records().map(move |record| {
+        deserialize(
+            record.wrap_err("failed to read log line"),
+            headers.as_ref(),
+            unknown.as_deref(),
+        )
+    }))
+}
+
+/// Read every row of a parquet file as if it was a text log line, so records are deserialized
+/// the same way no matter how they were delivered. Parquet needs random access, so the whole file
+/// is loaded in memory.
+pub(crate) fn read_parquet<T>(
+    mut file: impl Read,
+    fields: &'static [&'static str],
+) -> Result<impl Iterator<Item = LineResult<T>>>
+where
+    T: DeserializeOwned,
+{
+    let mut data = vec![];
+    file.read_to_end(&mut data)
+        .wrap_err("failed to read parquet file")?;
+    let reader =
+        SerializedFileReader::new(Bytes::from(data)).wrap_err("failed to open parquet file")?;
+    let headers: StringRecord = reader
+        .metadata()
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|column| column.name())
+        .collect();
+    let unknown = unknown_columns(&headers, fields);
+
+    Ok(RowIter::from_file_into(Box::new(reader)).map(move |row| {
+        let record = row
+            .map(|row| to_record(&row))
+            .wrap_err("failed to read parquet row");
+        deserialize(record, Some(&headers), unknown.as_deref())
+    }))
+}
+
+/// Describe the columns of `headers` which are not in `fields`, if there are any.
+fn unknown_columns(headers: &StringRecord, fields: &[&str]) -> Option<String> {
+    if fields.is_empty() {
+        return None;
+    }
+    let unknown: Vec<_> = headers
+        .iter()
+        .filter(|column| !fields.contains(&column.replace('_', "-").as_str()))
+        .collect();
+    if unknown.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "unknown fields in the header: {}",
+        unknown.join(" ")
+    ))
+}
+
+/// `unknown` is set when the header itself is wrong, which makes every line bad.
+fn deserialize<T>(
+    record: Result<StringRecord>,
+    headers: Option<&StringRecord>,
+    unknown: Option<&str>,
+) -> LineResult<T>
+where
+    T: DeserializeOwned,
+{
+    let record = record.map_err(|error| BadLine { raw: None, error })?;
+    if let Some(unknown) = unknown {
+        return Err(BadLine {
+            raw: Some(to_raw(&record)),
+            error: eyre!("{}", unknown),
+        });
+    }
+    if let Some(headers) = headers {
+        if record.len() != headers.len() {
+            return Err(BadLine {
+                raw: Some(to_raw(&record)),
+                error: eyre!(
+                    "found {} fields but the header has {}",
+                    record.len(),
+                    headers.len()
+                ),
+            });
+        }
+    }
+    record.deserialize(headers).map_err(|error| BadLine {
+        raw: Some(to_raw(&record)),
+        error: Report::new(error).wrap_err("failed to parse log line"),
+    })
+}
+
+/// Rebuild the line a record was read from, quoting fields that need it.
+fn to_raw(record: &StringRecord) -> String {
+    let fields: Vec<_> = record
+        .iter()
+        .map(|field| {
+            if field.is_empty() || field.contains(' ') {
+                format!("\"{}\"", field)
+            } else {
+                field.to_owned()
+            }
+        })
+        .collect();
+    fields.join(" ")
+}
+
+/// Missing values are written as `-`, like in text logs.
+fn to_record(row: &Row) -> StringRecord {
+    row.get_column_iter()
+        .map(|(_, field)| match field {
+            Field::Null => "-".to_owned(),
+            Field::Str(value) => value.clone(),
+            field => field.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::StringRecord;
+
+    use super::{to_raw, unknown_columns, LogFormat};
+    use crate::log_types::{FlowLogLine, Searchable};
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(LogFormat::detect("a.log", b"2 1234"), LogFormat::Text);
+        assert_eq!(
+            LogFormat::detect("a.log.gz", &[0x1f, 0x8b]),
+            LogFormat::Gzip
+        );
+        assert_eq!(LogFormat::detect("a.log.parquet", b""), LogFormat::Parquet);
+        assert_eq!(LogFormat::detect("-", b"PAR1\x15"), LogFormat::Parquet);
+    }
+
+    #[test]
+    fn test_raw_line() {
+        let record = StringRecord::from(vec!["http", "curl/7.46.0 (linux)", "", "-"]);
+        assert_eq!(to_raw(&record), r#"http "curl/7.46.0 (linux)" "" -"#);
+    }
+
+    #[test]
+    fn test_unknown_columns() {
+        let fields = FlowLogLine::header_fields();
+        let header = |columns: &[&str]| StringRecord::from(columns.to_vec());
+        assert_eq!(
+            unknown_columns(&header(&["version", "log-status"]), fields),
+            None
+        );
+        assert_eq!(
+            unknown_columns(&header(&["account_id", "type"]), fields),
+            None
+        );
+        assert_eq!(
+            unknown_columns(
+                &header(&["version", "srcaddr", "elb", "client:port"]),
+                fields
+            ),
+            Some("unknown fields in the header: elb client:port".to_owned())
+        );
+        assert_eq!(unknown_columns(&header(&["anything"]), &[]), None);
+    }
+}
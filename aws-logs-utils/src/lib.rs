@@ -1,163 +1,944 @@
-/// TODO: Disclaimer, many parts of this code will still block so some refactoring is needed
-use std::collections::HashSet;
+/// Log files are downloaded and filtered by a pool of worker threads, each one still blocks while
+/// downloading its own file.
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
-use std::io::Read;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use color_eyre::eyre::{Result, WrapErr};
+use aws_sdk_s3::Client;
+use color_eyre::eyre::{eyre, Result, WrapErr};
 use color_eyre::Report;
 use flate2::read::MultiGzDecoder;
-use futures::executor::block_on;
-use log::{debug, info, trace};
-use rusoto_s3::{S3Client, S3};
+use log::{debug, info, trace, warn};
 use serde::de::DeserializeOwned;
-use wirefilter::FilterAst;
+use serde::Serialize;
+use tokio::runtime::Handle;
+use wirefilter::{Filter, FilterAst};
 
-use aws_s3_utils::BucketKeyIterator;
+use aws_cli_common::format_size;
 
+use crate::checkpoint::Checkpoint;
+use crate::follow::HighWaterMark;
+use crate::format::{LineResult, LogFormat};
 use crate::log_types::Searchable;
+use crate::output::{Match, Output, OutputFormat};
+use crate::source::{LogFile, Source};
+use crate::stats::Stats;
+use crate::time_range::TimeRange;
 
+pub mod aggregate;
+pub mod checkpoint;
+pub mod follow;
+pub mod format;
 pub mod log_types;
+pub mod output;
+pub mod queries;
+pub mod source;
+pub mod stats;
+pub mod time_range;
+
+/// How many parsed lines are checked against one compilation of the filter.
+pub const FILTER_BATCH_SIZE: usize = 1024;
 
 lazy_static::lazy_static! {
-    static ref INTERESTING_ERRORS: HashSet<u16> = (501..=504).collect();
-    static ref IGNORED_ELB_ERRORS: HashSet<u16> = (300..=460).collect();
+    /// Status codes that mean the load balancer could not get a proper answer from a target.
+    pub static ref INTERESTING_ERRORS: HashSet<u16> = (501..=504).collect();
+    /// Redirects and client errors which are not worth looking at when hunting target errors.
+    pub static ref IGNORED_ELB_ERRORS: HashSet<u16> = (300..=460).collect();
+}
+
+/// Searches log files of type `S` for records matching a filter.
+///
+/// The filter is compiled once per batch of [`FILTER_BATCH_SIZE`] lines in each worker, not once
+/// per parser. Only the query is parsed once: wirefilter ties a compiled filter to the lifetime of
+/// the lines it checks, so it cannot be kept across batches.
+pub struct Parser<'a, S> {
+    source: Source<'a>,
+    filter: FilterAst<'static>,
+    output_format: OutputFormat,
+    concurrency: usize,
+    largest_first: bool,
+    time_range: TimeRange,
+    strict: bool,
+    rejected_lines: Option<PathBuf>,
+    checkpoint: Option<PathBuf>,
+    resume: bool,
+    record: PhantomData<fn() -> S>,
 }
 
-pub struct Parser<'a> {
-    client: &'a S3Client,
-    bucket: &'a str,
-    prefix: &'a str,
-    filter_query: &'static str,
+/// Matches of a single log file together with its line counts.
+struct Processed<S> {
+    key: String,
+    matches: Vec<Match<S>>,
+    stats: Stats,
+    rejected: Vec<String>,
 }
 
-impl<'a> Parser<'a> {
+impl<'a, S> Parser<'a, S>
+where
+    S: Searchable + DeserializeOwned + Debug + Send,
+{
     pub fn new(
-        client: &'a S3Client,
+        client: &'a Client,
         bucket: &'a str,
         prefix: &'a str,
-        filter_query: &'static str,
-    ) -> Self {
-        Self {
-            client,
-            bucket,
-            prefix,
-            filter_query,
-        }
+        filter_query: &str,
+    ) -> Result<Self> {
+        Self::from_source(Source::s3(client, bucket, prefix), filter_query)
+    }
+
+    /// The filter query is parsed right away, so a typo is reported before anything is listed.
+    pub fn from_source(source: Source<'a>, filter_query: &str) -> Result<Self> {
+        let filter = S::scheme()
+            .parse(filter_query)
+            .map_err(|e| eyre!("{}", e))
+            .wrap_err("failed to parse filter query")?;
+        Ok(Self {
+            source,
+            filter,
+            output_format: OutputFormat::default(),
+            concurrency: 1,
+            largest_first: false,
+            time_range: TimeRange::default(),
+            strict: false,
+            rejected_lines: None,
+            checkpoint: None,
+            resume: false,
+            record: PhantomData,
+        })
+    }
+
+    /// Fail on the first line which cannot be parsed instead of counting and skipping it.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Write the lines which could not be parsed to a file so they can be looked at later.
+    pub fn with_rejected_lines(mut self, path: Option<PathBuf>) -> Self {
+        self.rejected_lines = path;
+        self
+    }
+
+    /// Record the files which were completely processed in this file, see `Checkpoint`.
+    pub fn with_checkpoint(mut self, path: Option<PathBuf>) -> Self {
+        self.checkpoint = path;
+        self
+    }
+
+    /// Skip the files already recorded in the checkpoint and append to it and to the rejected
+    /// lines instead of starting over.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Skip files named after a time outside of the range and records which do not overlap it.
+    pub fn with_time_range(mut self, time_range: TimeRange) -> Self {
+        self.time_range = time_range;
+        self
+    }
+
+    /// Number of log files downloaded and filtered at the same time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Process the biggest files first so a single huge file does not delay the end of the scan,
+    /// by default files are processed in the order they were listed.
+    pub fn with_largest_first(mut self, largest_first: bool) -> Self {
+        self.largest_first = largest_first;
+        self
+    }
+
+    /// Print every matched line to stdout using the configured output format.
+    pub fn parse_logs(&self) -> Result<Stats>
+    where
+        S: Serialize,
+    {
+        let mut output = self.output_format.writer::<S, _>(io::stdout());
+        self.parse_logs_with_output(output.as_mut())
     }
 
-    pub fn parse_logs<S>(&self) -> Result<()>
+    /// Call `visit` with every match, in the same order `parse_logs_with_output` would write
+    /// them. Files are still processed concurrently.
+    pub fn for_each_match<F>(&self, visit: F) -> Result<Stats>
     where
-        S: Searchable + DeserializeOwned + Debug,
+        F: FnMut(&Match<S>) -> Result<()>,
     {
+        self.parse_logs_with_output(&mut Visitor(visit))
+    }
+
+    /// Iterate over the matches without any extra thread, files are only opened once the matches
+    /// of the previous one were consumed. `concurrency` is ignored.
+    pub fn matches(&self) -> Result<Matches<'_, 'a, S>> {
+        let checkpoint = self.open_checkpoint()?;
+        Ok(Matches {
+            files: self
+                .list_files(&HighWaterMark::default(), &checkpoint)?
+                .into_iter(),
+            current: vec![].into_iter(),
+            current_key: None,
+            rejected: self.create_rejected_lines()?,
+            checkpoint,
+            stats: Stats::default(),
+            parser: self,
+        })
+    }
+
+    /// Matches are written file by file in the same order the files are processed, no matter how
+    /// many of them are processed concurrently. Returns how many lines were parsed, matched and
+    /// could not be parsed.
+    pub fn parse_logs_with_output(&self, output: &mut dyn Output<S>) -> Result<Stats> {
+        let mut checkpoint = self.open_checkpoint()?;
+        let files = self.list_files(&HighWaterMark::default(), &checkpoint)?;
+        let mut rejected = self.create_rejected_lines()?;
+        let mut stats = Stats::default();
+        self.process_files(files, output, &mut stats, &mut rejected, &mut checkpoint)?;
+        output.finish()?;
+        Ok(stats)
+    }
+
+    /// Like `tail -f`, list the source every `interval` and only filter the files which appeared
+    /// since the last listing. The files already there are skipped unless `from_start` is set,
+    /// the time range still applies to them. Only returns on errors, `finish` is never called so
+    /// outputs which need all matches at once will not print anything.
+    pub fn follow(
+        &self,
+        output: &mut dyn Output<S>,
+        interval: Duration,
+        from_start: bool,
+    ) -> Result<()> {
+        if let Source::Stdin = self.source {
+            return Err(eyre!("Logs coming through stdin cannot be followed"));
+        }
+        let mut checkpoint = self.open_checkpoint()?;
+        let mut rejected = self.create_rejected_lines()?;
+        let mut total = Stats::default();
+        let mut mark = HighWaterMark::default();
+        if !from_start {
+            mark.advance(&self.source.files()?);
+            info!(
+                "Skipped the files already there, following new files modified after {}",
+                mark.last_modified()
+                    .map_or_else(|| "-".to_owned(), |time| time.to_rfc3339())
+            );
+        }
+
+        loop {
+            let files = self.list_files(&mark, &checkpoint)?;
+            if !files.is_empty() {
+                let mut stats = Stats::default();
+                self.process_files(
+                    files.clone(),
+                    output,
+                    &mut stats,
+                    &mut rejected,
+                    &mut checkpoint,
+                )?;
+                output.flush()?;
+                mark.advance(&files);
+                total.merge(&stats);
+                info!("{}", stats);
+                debug!("Since following: {}", total);
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    fn process_files(
+        &self,
+        files: Vec<LogFile>,
+        output: &mut dyn Output<S>,
+        stats: &mut Stats,
+        rejected: &mut Option<BufWriter<File>>,
+        checkpoint: &mut Option<Checkpoint>,
+    ) -> Result<()> {
+        // Workers are plain threads, they need the runtime handle so S3 downloads can do IO
+        let runtime = Handle::try_current().ok();
+
+        let (work_sender, work_receiver) = mpsc::sync_channel::<(usize, LogFile)>(0);
+        let work_receiver = Mutex::new(work_receiver);
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        // The senders are moved into the scope so workers are released on early returns
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency {
+                let work_receiver = &work_receiver;
+                let result_sender = result_sender.clone();
+                let runtime = &runtime;
+                scope.spawn(move || {
+                    let _guard = runtime.as_ref().map(Handle::enter);
+                    loop {
+                        let job = work_receiver.lock().unwrap().recv();
+                        let (index, file) = match job {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+                        let processed = self.process_log_file(&file);
+                        if result_sender.send((index, processed)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(result_sender);
+
+            // Only keep a limited number of finished files in memory waiting for a slow one
+            let window = self.concurrency * 2;
+            let mut pending = BTreeMap::new();
+            let mut next_index = 0;
+            let mut write_in_order = |pending: &mut BTreeMap<_, _>, next_index: &mut usize| {
+                Self::write_in_order(pending, next_index, output, stats, rejected, checkpoint)
+            };
+            for (index, file) in files.into_iter().enumerate() {
+                while index >= next_index + window {
+                    let (done, processed) = result_receiver.recv()?;
+                    pending.insert(done, processed);
+                    write_in_order(&mut pending, &mut next_index)?;
+                }
+                work_sender.send((index, file))?;
+                while let Ok((done, processed)) = result_receiver.try_recv() {
+                    pending.insert(done, processed);
+                }
+                write_in_order(&mut pending, &mut next_index)?;
+            }
+            drop(work_sender);
+
+            for (done, processed) in result_receiver {
+                pending.insert(done, processed);
+                write_in_order(&mut pending, &mut next_index)?;
+            }
+            Ok::<_, Report>(())
+        })
+    }
+
+    fn write_in_order(
+        pending: &mut BTreeMap<usize, Result<Processed<S>>>,
+        next_index: &mut usize,
+        output: &mut dyn Output<S>,
+        stats: &mut Stats,
+        rejected: &mut Option<BufWriter<File>>,
+        checkpoint: &mut Option<Checkpoint>,
+    ) -> Result<()> {
+        while let Some(processed) = pending.remove(next_index) {
+            let processed = processed?;
+            for matched in &processed.matches {
+                output.write_match(matched)?;
+            }
+            Self::write_rejected_lines(rejected, &processed.rejected)?;
+            if let Some(checkpoint) = checkpoint.as_mut() {
+                // The matches have to be out before the file is marked as done
+                output.flush()?;
+                checkpoint.record(&processed.key)?;
+            }
+            stats.merge(&processed.stats);
+            *next_index += 1;
+        }
+        Ok(())
+    }
+
+    /// List the files to process, leaving out the ones already behind `mark` or done according
+    /// to the checkpoint.
+    fn list_files(
+        &self,
+        mark: &HighWaterMark,
+        checkpoint: &Option<Checkpoint>,
+    ) -> Result<Vec<LogFile>> {
         debug!("Starting process");
-        let scheme = S::scheme();
-        let ast = scheme
-            .parse(self.filter_query)
-            .wrap_err("failed to parse filter query")?;
-        let mut iterator = BucketKeyIterator::new(self.bucket, Some(self.prefix), self.client);
+        let mut files = mark.new_files(self.source.files()?);
+        if let Some(checkpoint) = checkpoint {
+            let listed = files.len();
+            files.retain(|file| !checkpoint.contains(&file.key));
+            if files.len() < listed {
+                info!(
+                    "Skipping {} files already processed according to the checkpoint",
+                    listed - files.len()
+                );
+            }
+        }
+        if !self.time_range.is_unbounded() {
+            files.retain(|file| self.time_range.may_contain_file(&file.key));
+        }
+        if self.largest_first {
+            files.sort_by_key(|file| Reverse(file.size));
+        }
+        let total_size: u64 = files.iter().filter_map(|file| file.size).sum();
+        info!(
+            "Scanning {} files, {} in total",
+            files.len(),
+            format_size(total_size)
+        );
+        Ok(files)
+    }
+
+    fn open_checkpoint(&self) -> Result<Option<Checkpoint>> {
+        self.checkpoint
+            .as_deref()
+            .map(|path| Checkpoint::open(path, self.resume))
+            .transpose()
+    }
 
-        while let Some(key) = block_on(iterator.iter_next())? {
-            self.process_log_file::<S>(&key, &ast)?;
+    fn create_rejected_lines(&self) -> Result<Option<BufWriter<File>>> {
+        match &self.rejected_lines {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(self.resume)
+                    .truncate(!self.resume)
+                    .open(path)
+                    .wrap_err_with(|| format!("failed to create {}", path.display()))?;
+                Ok(Some(BufWriter::new(file)))
+            }
+            None => Ok(None),
         }
+    }
 
+    fn write_rejected_lines(
+        rejected: &mut Option<BufWriter<File>>,
+        lines: &[String],
+    ) -> Result<()> {
+        if let Some(rejected) = rejected.as_mut() {
+            for line in lines {
+                writeln!(rejected, "{}", line)?;
+            }
+            rejected.flush()?;
+        }
         Ok(())
     }
 
-    fn process_log_file<'ast, S>(&self, key: &str, ast: &'ast FilterAst<'a>) -> Result<()>
-    where
-        S: Searchable + DeserializeOwned + Debug,
-    {
-        let data = self.process_s3_file::<S>(key, true);
-        debug!("Processing {}", key);
+    fn process_log_file(&self, file: &LogFile) -> Result<Processed<S>> {
+        let has_headers = S::has_headers();
+        let data = self.source.open(file)?;
+        let mut processed = Processed {
+            key: file.key.clone(),
+            matches: vec![],
+            stats: Stats {
+                files: 1,
+                ..Default::default()
+            },
+            rejected: vec![],
+        };
+        debug!("Processing {}", file.key);
 
-        if let Some(lines) = data {
-            for line in lines? {
+        if let Some(data) = data {
+            let mut batch = Vec::with_capacity(FILTER_BATCH_SIZE);
+            let lines = Self::read_log_file::<S, _>(&file.key, data, has_headers)?;
+            for (index, line) in lines.enumerate() {
+                let line_number = index as u64 + 1 + has_headers as u64;
                 let line = match line {
                     Ok(line) => line,
-                    Err(e) => {
-                        debug!("Skipping line because of {:?}", e);
+                    Err(bad_line) => {
+                        let error = format!("{}:{}: {:#}", file.key, line_number, bad_line.error);
+                        if self.strict {
+                            return Err(eyre!(error));
+                        }
+                        debug!("Skipping line {}", error);
+                        processed.stats.add_error(error);
+                        if self.rejected_lines.is_some() {
+                            processed.rejected.extend(bad_line.raw);
+                        }
                         continue;
                     }
                 };
-                Self::process_log_line(line, ast)?;
+                processed.stats.parsed += 1;
+                let matched = Match {
+                    key: file.key.clone(),
+                    line_number,
+                    record: line,
+                };
+                batch.push(matched);
+                if batch.len() == FILTER_BATCH_SIZE {
+                    self.filter_batch(&mut batch, &mut processed)?;
+                }
             }
+            self.filter_batch(&mut batch, &mut processed)?;
         }
 
-        Ok(())
+        let stats = &processed.stats;
+        if stats.failed > 0 {
+            warn!("{}: {} lines could not be parsed", file.key, stats.failed);
+        }
+        info!(
+            "{}: {} lines parsed, {} matched, {} failed",
+            file.key, stats.parsed, stats.matched, stats.failed
+        );
+        Ok(processed)
     }
 
-    fn process_s3_file<T>(
-        &self,
+    /// Read log records from space delimited lines, which can be gzipped, or from a parquet file.
+    /// The format is detected from the key and the first bytes of the file.
+    fn read_log_file<'r, T, R>(
         key: &str,
+        file: R,
         has_headers: bool,
-    ) -> Option<Result<impl Iterator<Item = std::result::Result<T, csv::Error>>>>
+    ) -> Result<Box<dyn Iterator<Item = LineResult<T>> + 'r>>
     where
-        T: DeserializeOwned,
+        T: Searchable + DeserializeOwned + 'r,
+        R: Read + 'r,
     {
-        debug!("Starting to download from s3://{}/{}", self.bucket, key);
-        let request = rusoto_s3::GetObjectRequest {
-            bucket: self.bucket.to_owned(),
-            key: key.to_owned(),
-            ..Default::default()
+        let mut file = BufReader::new(file);
+        let log_format =
+            LogFormat::detect(key, file.fill_buf().wrap_err("failed to read log file")?);
+        let file: Box<dyn Read + 'r> = match log_format {
+            LogFormat::Parquet => {
+                return Ok(Box::new(format::read_parquet(file, T::header_fields())?))
+            }
+            LogFormat::Gzip => Box::new(MultiGzDecoder::new(file)),
+            LogFormat::Text => Box::new(file),
         };
-        let response = match block_on(self.client.get_object(request))
-            .wrap_err("Error downloading log file")
-        {
-            Ok(response) => response,
-            Err(e) => return Some(Err(e)),
+        Ok(Box::new(format::read_text(
+            file,
+            has_headers,
+            T::header_fields(),
+        )?))
+    }
+
+    /// Move the lines of `batch` which match into `processed`.
+    fn filter_batch(&self, batch: &mut Vec<Match<S>>, processed: &mut Processed<S>) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let is_match = {
+            let filter = self.filter.clone().compile();
+            batch
+                .iter()
+                .map(|matched| self.process_log_line(matched, &filter))
+                .collect::<Result<Vec<_>>>()?
         };
+        for (matched, is_match) in batch.drain(..).zip(is_match) {
+            if is_match {
+                processed.stats.matched += 1;
+                processed.matches.push(matched);
+            }
+        }
+        Ok(())
+    }
 
-        if let Some(body) = response.body {
-            debug!(
-                "Processing bucket: {} key: {} size: {}",
-                self.bucket,
-                key,
-                human_format::Formatter::new()
-                    .with_scales(human_format::Scales::Binary())
-                    .with_units("B")
-                    .format(response.content_length.unwrap() as f64)
-            );
-            let stream =
-                Self::read_log_file(MultiGzDecoder::new(body.into_blocking_read()), has_headers);
-            return Some(Ok(stream));
-        } else {
-            info!(
-                "Nothing useful returned from s3 file bucket: {} key: {} response: {:?}",
-                self.bucket, key, response
-            )
+    fn process_log_line<'f>(&self, matched: &'f Match<S>, filter: &Filter<'f>) -> Result<bool> {
+        if let Some((start, end)) = matched.record.time_span() {
+            if !self.time_range.overlaps(start, end) {
+                trace!("Out of the time range {:#?}", matched);
+                return Ok(false);
+            }
         }
+        let ctx = matched
+            .record
+            .execution_context()
+            .wrap_err("error building execution context")?;
+        if filter.execute(&ctx).map_err(Report::msg)? {
+            return Ok(true);
+        }
+        trace!("NOT Matched with {:#?}", matched);
+        Ok(false)
+    }
+}
 
-        None
+/// Lets a closure be used as an `Output`.
+struct Visitor<F>(F);
+
+impl<S, F> Output<S> for Visitor<F>
+where
+    F: FnMut(&Match<S>) -> Result<()>,
+{
+    fn write_match(&mut self, matched: &Match<S>) -> Result<()> {
+        (self.0)(matched)
     }
+}
 
-    fn read_log_file<T, R>(
-        file: R,
-        has_headers: bool,
-    ) -> impl Iterator<Item = Result<T, csv::Error>>
-    where
-        T: DeserializeOwned,
-        R: Read,
-    {
-        csv::ReaderBuilder::new()
-            .delimiter(b' ')
-            .has_headers(has_headers)
-            .from_reader(file)
-            .into_deserialize()
+/// Matches of all log files in order, see `Parser::matches`. `stats` is complete once the
+/// iterator is exhausted.
+pub struct Matches<'p, 'a, S> {
+    parser: &'p Parser<'a, S>,
+    files: std::vec::IntoIter<LogFile>,
+    current: std::vec::IntoIter<Match<S>>,
+    /// Recorded in the checkpoint once all of its matches were returned
+    current_key: Option<String>,
+    rejected: Option<BufWriter<File>>,
+    checkpoint: Option<Checkpoint>,
+    stats: Stats,
+}
+
+impl<'p, 'a, S> Matches<'p, 'a, S> {
+    pub fn stats(&self) -> &Stats {
+        &self.stats
     }
+}
 
-    fn process_log_line<S>(line: S, ast: &FilterAst) -> Result<()>
-    where
-        S: Searchable + DeserializeOwned + Debug,
-    {
-        let filter = ast.clone().compile();
-        let ctx = line
-            .execution_context()
-            .wrap_err("error building execution context")?;
-        if filter.execute(&ctx).map_err(Report::msg)? {
-            println!("Matched with {:#?}", line);
-        } else {
-            trace!("NOT Matched with {:#?}", line);
+impl<'p, 'a, S> Iterator for Matches<'p, 'a, S>
+where
+    S: Searchable + DeserializeOwned + Debug + Send,
+{
+    type Item = Result<Match<S>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(matched) = self.current.next() {
+                return Some(Ok(matched));
+            }
+            if let (Some(checkpoint), Some(key)) = (self.checkpoint.as_mut(), &self.current_key) {
+                if let Err(e) = checkpoint.record(key) {
+                    return Some(Err(e));
+                }
+            }
+            self.current_key = None;
+            let file = self.files.next()?;
+            let processed = match self.parser.process_log_file(&file) {
+                Ok(processed) => processed,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Err(e) =
+                Parser::<S>::write_rejected_lines(&mut self.rejected, &processed.rejected)
+            {
+                return Some(Err(e));
+            }
+            self.stats.merge(&processed.stats);
+            self.current = processed.matches.into_iter();
+            self.current_key = Some(processed.key);
         }
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::Arc;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use parquet::column::writer::ColumnWriter;
+    use parquet::data_type::ByteArray;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use chrono::{TimeZone, Utc};
+    use color_eyre::eyre::Result;
+
+    use crate::log_types::FlowLogLine;
+    use crate::output::{Match, Output};
+    use crate::source::Source;
+    use crate::time_range::TimeRange;
+    use crate::Parser;
+
+    const FLOW_LOG: &str = "version account-id interface-id srcaddr dstaddr srcport dstport protocol packets bytes start end action log-status
+2 123456789010 eni-1235b8ca123456789 172.31.16.139 172.31.16.21 20641 22 6 20 4249 1418530010 1418530070 ACCEPT OK
+2 123456789010 eni-1235b8ca123456789 172.31.9.69 172.31.9.12 49761 3389 6 20 4249 1418530010 1418530070 REJECT OK
+";
+
+    fn read(key: &str, data: &[u8]) -> Vec<FlowLogLine> {
+        Parser::<FlowLogLine>::read_log_file(key, data, true)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read_plain_log_file() {
+        let lines = read("flow.log", FLOW_LOG.as_bytes());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].action.as_deref(), Some("REJECT"));
+    }
+
+    #[test]
+    fn test_read_gzipped_log_file() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(FLOW_LOG.as_bytes()).unwrap();
+        let lines = read("flow.log.gz", &encoder.finish().unwrap());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].dstport, Some(22));
+    }
+
+    #[test]
+    fn test_read_parquet_log_file() {
+        let rows: Vec<Vec<&str>> = FLOW_LOG
+            .lines()
+            .skip(1)
+            .map(|line| line.split(' ').collect())
+            .collect();
+        let schema = parse_message_type(
+            "message flow_log {
+                REQUIRED INT64 version;
+                REQUIRED BINARY account_id (UTF8);
+                REQUIRED BINARY interface_id (UTF8);
+                REQUIRED BINARY srcaddr (UTF8);
+                REQUIRED BINARY dstaddr (UTF8);
+                REQUIRED INT64 srcport;
+                REQUIRED INT64 dstport;
+                REQUIRED INT64 protocol;
+                REQUIRED INT64 packets;
+                REQUIRED INT64 bytes;
+                REQUIRED INT64 start;
+                REQUIRED INT64 end;
+                REQUIRED BINARY action (UTF8);
+                REQUIRED BINARY log_status (UTF8);
+            }",
+        )
+        .unwrap();
+
+        let mut data = vec![];
+        let mut writer =
+            SerializedFileWriter::new(&mut data, Arc::new(schema), Default::default()).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column().unwrap() {
+            let values = rows.iter().map(|row| row[index]);
+            match column.untyped() {
+                ColumnWriter::Int64ColumnWriter(ref mut writer) => {
+                    let values: Vec<i64> = values.map(|value| value.parse().unwrap()).collect();
+                    writer.write_batch(&values, None, None).unwrap();
+                }
+                ColumnWriter::ByteArrayColumnWriter(ref mut writer) => {
+                    let values: Vec<ByteArray> = values.map(ByteArray::from).collect();
+                    writer.write_batch(&values, None, None).unwrap();
+                }
+                _ => unreachable!(),
+            }
+            column.close().unwrap();
+            index += 1;
+        }
+        row_group.close().unwrap();
+        writer.close().unwrap();
+
+        let lines = read("-", &data);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].action.as_deref(), Some("REJECT"));
+        assert_eq!(lines[1].protocol, Some(6));
+        assert_eq!(lines[0].start, Some(1418530010));
+    }
+
+    #[derive(Default)]
+    struct Collect(Vec<(String, u64)>);
+
+    impl Output<FlowLogLine> for Collect {
+        fn write_match(&mut self, matched: &Match<FlowLogLine>) -> Result<()> {
+            self.0.push((matched.key.clone(), matched.line_number));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_concurrent_output_is_ordered() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut expected = vec![];
+        for file_number in 0..10 {
+            let path = directory.path().join(format!("{:02}.log", file_number));
+            let mut file = std::fs::File::create(&path).unwrap();
+            file.write_all(FLOW_LOG.as_bytes()).unwrap();
+            // Bigger files first so later files tend to finish earlier
+            for _ in 0..(10 - file_number) * 100 {
+                writeln!(file, "{}", FLOW_LOG.lines().nth(1).unwrap()).unwrap();
+            }
+            expected.push((path.to_string_lossy().into_owned(), 3));
+        }
+
+        let root = directory.path().to_string_lossy().into_owned();
+        let parser =
+            Parser::<FlowLogLine>::from_source(Source::local(&root), r#"action == "REJECT""#)
+                .unwrap()
+                .with_concurrency(4);
+        let mut output = Collect::default();
+        parser.parse_logs_with_output(&mut output).unwrap();
+        assert_eq!(output.0, expected);
+    }
+
+    #[test]
+    fn test_time_range_skips_records() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("flow.log");
+        std::fs::write(&path, FLOW_LOG).unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let count = |time_range: TimeRange| {
+            let mut output = Collect::default();
+            Parser::<FlowLogLine>::from_source(Source::local(&path), "bytes > 0")
+                .unwrap()
+                .with_time_range(time_range)
+                .parse_logs_with_output(&mut output)
+                .unwrap();
+            output.0.len()
+        };
+        let at = |timestamp| Some(Utc.timestamp_opt(timestamp, 0).unwrap());
+
+        assert_eq!(count(TimeRange::default()), 2);
+        assert_eq!(count(TimeRange::new(at(1418530050), None)), 2);
+        assert_eq!(count(TimeRange::new(at(1418530071), None)), 0);
+        assert_eq!(count(TimeRange::new(None, at(1418530000))), 0);
+    }
+
+    #[test]
+    fn test_bad_lines() {
+        let bad_line =
+            "2 123456789010 eni-1 not-an-ip 172.31.9.12 49761 3389 6 20 4249 1418530010 1418530070 REJECT OK";
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("flow.log");
+        std::fs::write(&path, format!("{}{}\n", FLOW_LOG, bad_line)).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let rejected = directory.path().join("rejected.log");
+
+        let parser =
+            Parser::<FlowLogLine>::from_source(Source::local(&path), r#"action == "REJECT""#)
+                .unwrap()
+                .with_rejected_lines(Some(rejected.clone()));
+        let stats = parser
+            .parse_logs_with_output(&mut Collect::default())
+            .unwrap();
+        assert_eq!(
+            (stats.files, stats.parsed, stats.matched, stats.failed),
+            (1, 2, 1, 1)
+        );
+        assert!(stats.errors[0].starts_with(&format!("{}:4: ", path)));
+        assert_eq!(
+            std::fs::read_to_string(&rejected).unwrap(),
+            format!("{}\n", bad_line)
+        );
+
+        let strict = Parser::<FlowLogLine>::from_source(Source::local(&path), "bytes > 0")
+            .unwrap()
+            .with_strict(true);
+        assert!(strict
+            .parse_logs_with_output(&mut Collect::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_lines_not_matching_the_header() {
+        let truncated = "2 123456789010 eni-1235b8ca123456789 172.31.9.69";
+        let extra = "2 123456789010 eni-1235b8ca123456789 172.31.9.69 172.31.9.12 49761 3389 6 20 4249 1418530010 1418530070 REJECT OK extra";
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("flow.log");
+        std::fs::write(&path, format!("{}{}\n{}\n", FLOW_LOG, truncated, extra)).unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let parser = Parser::<FlowLogLine>::from_source(Source::local(&path), "bytes > 0").unwrap();
+        let stats = parser
+            .parse_logs_with_output(&mut Collect::default())
+            .unwrap();
+        assert_eq!((stats.parsed, stats.matched, stats.failed), (2, 2, 2));
+        assert!(stats.errors[0].starts_with(&format!("{}:4: found 4 fields", path)));
+
+        let strict = parser.with_strict(true);
+        assert!(strict
+            .parse_logs_with_output(&mut Collect::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_wrong_schema() {
+        let directory = tempfile::tempdir().unwrap();
+        let alb_log = directory.path().join("alb.log");
+        let alb_line = "http 2018-07-02T22:23:00.186641Z app/my-loadbalancer/50dc6c495c0c9188 192.168.131.39:2817 10.0.0.1:80 0.000 1.501 0.000 502 502 34 366";
+        std::fs::write(&alb_log, format!("{}\n{}\n", alb_line, alb_line)).unwrap();
+        let unknown_field = directory.path().join("unknown.log");
+        std::fs::write(&unknown_field, "version srcaddr color\n2 10.0.0.2 blue\n").unwrap();
+
+        for path in &[alb_log, unknown_field] {
+            let path = path.to_string_lossy().into_owned();
+            let parser =
+                Parser::<FlowLogLine>::from_source(Source::local(&path), "bytes > 0").unwrap();
+            let stats = parser
+                .parse_logs_with_output(&mut Collect::default())
+                .unwrap();
+            assert_eq!((stats.parsed, stats.failed), (0, 1));
+            assert!(stats.errors[0].contains("unknown fields in the header"));
+
+            let strict = parser.with_strict(true);
+            assert!(strict
+                .parse_logs_with_output(&mut Collect::default())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_matches_iterator() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in &["a.log", "b.log"] {
+            std::fs::write(directory.path().join(name), FLOW_LOG).unwrap();
+        }
+        let root = directory.path().to_string_lossy().into_owned();
+        // The query does not have to outlive the parser
+        let query = format!("dstport == {}", 3389);
+        let parser = Parser::<FlowLogLine>::from_source(Source::local(&root), &query).unwrap();
+
+        let mut matches = parser.matches().unwrap();
+        let lines: Vec<_> = matches
+            .by_ref()
+            .map(|matched| matched.unwrap().line_number)
+            .collect();
+        assert_eq!(lines, vec![3, 3]);
+        assert_eq!(matches.stats().files, 2);
+        assert_eq!(matches.stats().parsed, 4);
+
+        let mut visited = vec![];
+        let stats = parser
+            .for_each_match(|matched| {
+                visited.push(matched.record.srcport);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(visited, vec![Some(49761), Some(49761)]);
+        assert_eq!(stats.matched, 2);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let directory = tempfile::tempdir().unwrap();
+        let logs = directory.path().join("logs");
+        std::fs::create_dir(&logs).unwrap();
+        for name in &["a.log", "b.log", "c.log"] {
+            std::fs::write(logs.join(name), FLOW_LOG).unwrap();
+        }
+        let root = logs.to_string_lossy().into_owned();
+        let checkpoint = directory.path().join("checkpoint");
+        let parser = |resume| {
+            Parser::<FlowLogLine>::from_source(Source::local(&root), "dstport == 22")
+                .unwrap()
+                .with_concurrency(2)
+                .with_checkpoint(Some(checkpoint.clone()))
+                .with_resume(resume)
+        };
+
+        // Interrupted while the match of the second file is being handled
+        let interrupted = parser(false);
+        let mut matches = interrupted.matches().unwrap();
+        assert!(matches.next().unwrap().is_ok());
+        assert!(matches.next().unwrap().is_ok());
+        drop(matches);
+        let done = std::fs::read_to_string(&checkpoint).unwrap();
+        assert_eq!(done.lines().count(), 1);
+        assert!(done.contains("a.log"));
+
+        let mut output = Collect::default();
+        let stats = parser(true).parse_logs_with_output(&mut output).unwrap();
+        assert_eq!(stats.files, 2);
+        assert_eq!(
+            output
+                .0
+                .iter()
+                .map(|(key, _)| key.rsplit('/').next().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["b.log", "c.log"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&checkpoint)
+                .unwrap()
+                .lines()
+                .count(),
+            3
+        );
+        let stats = parser(true).parse_logs_with_output(&mut output).unwrap();
+        assert_eq!(stats.files, 0);
     }
 }
@@ -1,101 +1,461 @@
-use std::net::IpAddr;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
 use color_eyre::{Report, Result};
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use wirefilter::{ExecutionContext, Scheme};
 
 pub trait Searchable {
     fn scheme() -> &'static Scheme;
-    fn execution_context(&self) -> Result<ExecutionContext>;
-}
-
-//#[derive(Debug, Deserialize, PartialEq)]
-//#[serde(untagged)]
-//pub(crate) enum OptionalNumber<'a, T>
-//where
-//    T: Deserialize<'a>,
-//{
-//    Duration(T),
-//    Failure(String),
-//}
-//
-//#[derive(Debug, PartialEq)]
-//pub(crate) struct Request {
-//    pub method: String,
-//    pub path: String,
-//    pub http_version: String,
-//}
-//
-//impl From<&str> for Request {
-//    fn from(data: &str) -> Self {
-//        let mut parts = data.split(" ");
-//        Request {
-//            method: parts.next().unwrap().to_owned(),
-//            path: parts.next().unwrap().to_owned(),
-//            http_version: parts.next().unwrap().to_owned(),
-//        }
-//    }
-//}
-//
-//#[derive(Debug, Deserialize)]
-//pub(crate) struct RequestLogLine {
-//    pub request_type: String,
-//    pub timestamp: DateTime<Utc>,
-//    pub elb_name: String,
-//    pub client: String,
-//    pub target: String,
-//    pub request_processing_time: OptionalNumber<f64>,
-//    pub target_processing_time: OptionalNumber<f64>,
-//    pub response_processing_time: OptionalNumber<f64>,
-//    pub elb_status_code: u16,
-//    pub target_status_code: OptionalNumber<u16>,
-//    pub received_bytes: u64,
-//    pub sent_bytes: u64,
-//    // method + url + http version
-//    request: String,
-//    pub user_agent: String,
-//    pub ssl_cipher: String,
-//    pub ssl_protocol: String,
-//    pub target_group_arn: String,
-//    pub trace_id: String,
-//    pub domain_name: String,
-//    pub chosen_cert_arn: String,
-//    pub matched_rule_priority: String,
-//    pub request_creation_time: DateTime<Utc>,
-//    pub actions_executed: String,
-//    pub error_reason: String,
-//}
-//
-//impl RequestLogLine {
-//    pub fn request(&self) -> Request {
-//        Request::from(self.request.as_str())
-//    }
-//}
-
-#[derive(Debug, Deserialize)]
+    /// Whether log files of this type start with a header line naming the fields.
+    fn has_headers() -> bool {
+        true
+    }
+    /// Names the columns of a header line can have, `_` and `-` being the same. Files with any
+    /// other column cannot be read. Empty when any header is accepted.
+    fn header_fields() -> &'static [&'static str] {
+        &[]
+    }
+    /// Start and end of the record in unix seconds, used to check it against a time range.
+    fn time_span(&self) -> Option<(i64, i64)> {
+        None
+    }
+    fn execution_context(&self) -> Result<ExecutionContext<'_>>;
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum OptionalNumber<T> {
+    Duration(T),
+    Failure(String),
+}
+
+impl<T: Copy> OptionalNumber<T> {
+    pub fn value(&self) -> Option<T> {
+        match self {
+            OptionalNumber::Duration(value) => Some(*value),
+            OptionalNumber::Failure(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    pub http_version: &'a str,
+}
+
+impl<'a> Request<'a> {
+    /// Host part of the url without scheme and port, e.g. `example.com`
+    pub fn host(&self) -> &'a str {
+        let authority = self.authority();
+        match authority.rfind(':') {
+            Some(index) if !authority.ends_with(']') => &authority[..index],
+            _ => authority,
+        }
+    }
+
+    /// Path and query of the url, e.g. `/users?page=2`
+    pub fn path(&self) -> &'a str {
+        let without_scheme = self.without_scheme();
+        without_scheme
+            .find('/')
+            .map(|index| &without_scheme[index..])
+            .unwrap_or("/")
+    }
+
+    fn authority(&self) -> &'a str {
+        self.without_scheme().split('/').next().unwrap_or_default()
+    }
+
+    fn without_scheme(&self) -> &'a str {
+        self.url
+            .split_once("://")
+            .map(|(_, rest)| rest)
+            .unwrap_or(self.url)
+    }
+}
+
+impl<'a> From<&'a str> for Request<'a> {
+    fn from(data: &'a str) -> Self {
+        let mut parts = data.split(' ');
+        let mut next = || parts.next().unwrap_or_default();
+        Request {
+            method: next(),
+            url: next(),
+            http_version: next(),
+        }
+    }
+}
+
+/// Application Load Balancer access log entry, the fields are positional as ALB does not write
+/// a header line.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RequestLogLine {
+    pub request_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub elb_name: String,
+    pub client: String,
+    pub target: String,
+    pub request_processing_time: OptionalNumber<f64>,
+    pub target_processing_time: OptionalNumber<f64>,
+    pub response_processing_time: OptionalNumber<f64>,
+    pub elb_status_code: u16,
+    pub target_status_code: OptionalNumber<u16>,
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+    // method + url + http version
+    request: String,
+    pub user_agent: String,
+    pub ssl_cipher: String,
+    pub ssl_protocol: String,
+    pub target_group_arn: String,
+    pub trace_id: String,
+    pub domain_name: String,
+    pub chosen_cert_arn: String,
+    pub matched_rule_priority: String,
+    pub request_creation_time: DateTime<Utc>,
+    pub actions_executed: String,
+    pub redirect_url: String,
+    pub error_reason: String,
+}
+
+impl RequestLogLine {
+    pub fn request(&self) -> Request<'_> {
+        Request::from(self.request.as_str())
+    }
+
+    pub fn client_addr(&self) -> Option<IpAddr> {
+        parse_addr(&self.client)
+    }
+
+    pub fn client_port(&self) -> Option<u16> {
+        parse_port(&self.client)
+    }
+
+    pub fn target_addr(&self) -> Option<IpAddr> {
+        parse_addr(&self.target)
+    }
+}
+
+/// Split `ip:port` as written by the load balancer, `-` means there was no value.
+fn split_addr(data: &str) -> Option<(&str, &str)> {
+    data.rsplit_once(':')
+        .map(|(addr, port)| (addr.trim_start_matches('[').trim_end_matches(']'), port))
+}
+
+fn parse_addr(data: &str) -> Option<IpAddr> {
+    split_addr(data).and_then(|(addr, _)| addr.parse().ok())
+}
+
+fn parse_port(data: &str) -> Option<u16> {
+    split_addr(data).and_then(|(_, port)| port.parse().ok())
+}
+
+/// Processing times are exposed to filters as milliseconds, as wirefilter has no float type.
+/// Failed requests, logged by the load balancer as -1, stay as -1.
+fn as_millis(time: &OptionalNumber<f64>) -> i32 {
+    match time.value() {
+        Some(seconds) if seconds >= 0.0 => (seconds * 1000.0).round() as i32,
+        _ => -1,
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref ALB_SCHEME: Scheme = Scheme! {
+        request_type: Bytes,
+        timestamp: Int,
+        elb: Bytes,
+
+        client_addr: Ip,
+        client_port: Int,
+        target: Bytes,
+
+        request_processing_time: Int,
+        target_processing_time: Int,
+        response_processing_time: Int,
+
+        elb_status_code: Int,
+        target_status_code: Int,
+
+        received_bytes: Int,
+        sent_bytes: Int,
+
+        method: Bytes,
+        host: Bytes,
+        path: Bytes,
+        http_version: Bytes,
+        user_agent: Bytes,
+
+        ssl_cipher: Bytes,
+        ssl_protocol: Bytes,
+        target_group_arn: Bytes,
+        domain_name: Bytes,
+        actions_executed: Bytes,
+        error_reason: Bytes,
+    };
+}
+
+impl Searchable for RequestLogLine {
+    fn scheme() -> &'static Scheme {
+        &ALB_SCHEME
+    }
+
+    fn has_headers() -> bool {
+        false
+    }
+
+    fn time_span(&self) -> Option<(i64, i64)> {
+        let timestamp = self.timestamp.timestamp();
+        Some((timestamp, timestamp))
+    }
+
+    fn execution_context(&self) -> Result<ExecutionContext<'_>> {
+        let mut ctx = ExecutionContext::new(Self::scheme());
+        let request = self.request();
+
+        ctx.set_field_value("request_type", self.request_type.as_str())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("timestamp", to_filter_int(self.timestamp.timestamp()))
+            .map_err(Report::msg)?;
+        ctx.set_field_value("elb", self.elb_name.as_str())
+            .map_err(Report::msg)?;
+
+        ctx.set_field_value(
+            "client_addr",
+            self.client_addr()
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        )
+        .map_err(Report::msg)?;
+        ctx.set_field_value(
+            "client_port",
+            self.client_port().map(i32::from).unwrap_or(-1),
+        )
+        .map_err(Report::msg)?;
+        ctx.set_field_value("target", self.target.as_str())
+            .map_err(Report::msg)?;
+
+        ctx.set_field_value(
+            "request_processing_time",
+            as_millis(&self.request_processing_time),
+        )
+        .map_err(Report::msg)?;
+        ctx.set_field_value(
+            "target_processing_time",
+            as_millis(&self.target_processing_time),
+        )
+        .map_err(Report::msg)?;
+        ctx.set_field_value(
+            "response_processing_time",
+            as_millis(&self.response_processing_time),
+        )
+        .map_err(Report::msg)?;
+
+        ctx.set_field_value("elb_status_code", i32::from(self.elb_status_code))
+            .map_err(Report::msg)?;
+        ctx.set_field_value(
+            "target_status_code",
+            self.target_status_code.value().map(i32::from).unwrap_or(-1),
+        )
+        .map_err(Report::msg)?;
+
+        ctx.set_field_value(
+            "received_bytes",
+            to_filter_int(i64::try_from(self.received_bytes).unwrap_or(i64::MAX)),
+        )
+        .map_err(Report::msg)?;
+        ctx.set_field_value(
+            "sent_bytes",
+            to_filter_int(i64::try_from(self.sent_bytes).unwrap_or(i64::MAX)),
+        )
+        .map_err(Report::msg)?;
+
+        ctx.set_field_value("method", request.method)
+            .map_err(Report::msg)?;
+        ctx.set_field_value("host", request.host())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("path", request.path())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("http_version", request.http_version)
+            .map_err(Report::msg)?;
+        ctx.set_field_value("user_agent", self.user_agent.as_str())
+            .map_err(Report::msg)?;
+
+        ctx.set_field_value("ssl_cipher", self.ssl_cipher.as_str())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("ssl_protocol", self.ssl_protocol.as_str())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("target_group_arn", self.target_group_arn.as_str())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("domain_name", self.domain_name.as_str())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("actions_executed", self.actions_executed.as_str())
+            .map_err(Report::msg)?;
+        ctx.set_field_value("error_reason", self.error_reason.as_str())
+            .map_err(Report::msg)?;
+
+        Ok(ctx)
+    }
+}
+
+/// Flow logs use `-` for fields which do not apply to a record, e.g. the addresses of a NODATA
+/// record or the vpc id of a flow which went through a transit gateway.
+fn absent<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)?.as_deref() {
+        None | Some("-") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(de::Error::custom),
+    }
+}
+
+/// A flow log record, fields are matched by the header line of the file so custom formats with
+/// any of the version 2 to 5 fields in any order are supported. Fields which are not part of the
+/// format or set to `-` are `None`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct FlowLogLine {
-    pub version: String,
-    #[serde(alias = "account-id")]
-    pub account_id: String,
-    #[serde(alias = "interface-id")]
-    pub interface_id: String,
-    pub srcaddr: IpAddr,
-    pub dstaddr: IpAddr,
-    pub srcport: i32,
-    pub dstport: i32,
-    pub protocol: String,
-    pub packets: String,
-    pub bytes: i32,
-
-    pub start: i32,
-    pub end: i32,
-    pub action: String,
-    #[serde(alias = "log-status")]
-    pub log_status: String,
+    #[serde(deserialize_with = "absent")]
+    pub version: Option<i32>,
+    #[serde(alias = "account-id", deserialize_with = "absent")]
+    pub account_id: Option<String>,
+    #[serde(alias = "interface-id", deserialize_with = "absent")]
+    pub interface_id: Option<String>,
+    #[serde(deserialize_with = "absent")]
+    pub srcaddr: Option<IpAddr>,
+    #[serde(deserialize_with = "absent")]
+    pub dstaddr: Option<IpAddr>,
+    #[serde(deserialize_with = "absent")]
+    pub srcport: Option<i32>,
+    #[serde(deserialize_with = "absent")]
+    pub dstport: Option<i32>,
+    #[serde(deserialize_with = "absent")]
+    /// IANA protocol number, see `protocol_name`
+    pub protocol: Option<u8>,
+    #[serde(deserialize_with = "absent")]
+    pub packets: Option<i64>,
+    #[serde(deserialize_with = "absent")]
+    pub bytes: Option<i64>,
+
+    #[serde(deserialize_with = "absent")]
+    pub start: Option<i64>,
+    #[serde(deserialize_with = "absent")]
+    pub end: Option<i64>,
+    #[serde(deserialize_with = "absent")]
+    pub action: Option<String>,
+    #[serde(alias = "log-status", deserialize_with = "absent")]
+    pub log_status: Option<String>,
+
+    // Version 3
+    #[serde(alias = "vpc-id", deserialize_with = "absent")]
+    pub vpc_id: Option<String>,
+    #[serde(alias = "subnet-id", deserialize_with = "absent")]
+    pub subnet_id: Option<String>,
+    #[serde(alias = "instance-id", deserialize_with = "absent")]
+    pub instance_id: Option<String>,
+    #[serde(alias = "tcp-flags", deserialize_with = "absent")]
+    pub tcp_flags: Option<i32>,
+    /// IPv4, IPv6 or EFA, named `type` in the log format
+    #[serde(alias = "type", deserialize_with = "absent")]
+    pub traffic_type: Option<String>,
+    #[serde(alias = "pkt-srcaddr", deserialize_with = "absent")]
+    pub pkt_srcaddr: Option<IpAddr>,
+    #[serde(alias = "pkt-dstaddr", deserialize_with = "absent")]
+    pub pkt_dstaddr: Option<IpAddr>,
+
+    // Version 4
+    #[serde(deserialize_with = "absent")]
+    pub region: Option<String>,
+    #[serde(alias = "az-id", deserialize_with = "absent")]
+    pub az_id: Option<String>,
+    #[serde(alias = "sublocation-type", deserialize_with = "absent")]
+    pub sublocation_type: Option<String>,
+    #[serde(alias = "sublocation-id", deserialize_with = "absent")]
+    pub sublocation_id: Option<String>,
+
+    // Version 5
+    #[serde(alias = "pkt-src-aws-service", deserialize_with = "absent")]
+    pub pkt_src_aws_service: Option<String>,
+    #[serde(alias = "pkt-dst-aws-service", deserialize_with = "absent")]
+    pub pkt_dst_aws_service: Option<String>,
+    #[serde(alias = "flow-direction", deserialize_with = "absent")]
+    pub flow_direction: Option<String>,
+    #[serde(alias = "traffic-path", deserialize_with = "absent")]
+    pub traffic_path: Option<i32>,
+}
+
+/// Fields of flow log versions 2 to 5, as named in the log format.
+const FLOW_LOG_FIELDS: &[&str] = &[
+    "version",
+    "account-id",
+    "interface-id",
+    "srcaddr",
+    "dstaddr",
+    "srcport",
+    "dstport",
+    "protocol",
+    "packets",
+    "bytes",
+    "start",
+    "end",
+    "action",
+    "log-status",
+    "vpc-id",
+    "subnet-id",
+    "instance-id",
+    "tcp-flags",
+    "type",
+    "pkt-srcaddr",
+    "pkt-dstaddr",
+    "region",
+    "az-id",
+    "sublocation-type",
+    "sublocation-id",
+    "pkt-src-aws-service",
+    "pkt-dst-aws-service",
+    "flow-direction",
+    "traffic-path",
+];
+
+impl FlowLogLine {
+    /// Name of the most common protocols, e.g. `tcp` for 6.
+    pub fn protocol_name(&self) -> Option<&'static str> {
+        let name = match self.protocol? {
+            1 => "icmp",
+            6 => "tcp",
+            17 => "udp",
+            47 => "gre",
+            50 => "esp",
+            51 => "ah",
+            58 => "icmpv6",
+            132 => "sctp",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+/// Filters only support 32 bit integers, bigger values are capped so comparing them with anything
+/// that fits in 32 bits still works.
+fn to_filter_int(value: i64) -> i32 {
+    value.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
 }
 
 lazy_static::lazy_static! {
+    /// Fields which are absent from a record are set to `-1`, `0.0.0.0` or `"-"` depending on
+    /// their type, the same way missing values look in load balancer logs.
     pub static ref FLOW_SCHEME: Scheme = Scheme! {
+        version: Int,
+        account_id: Bytes,
+        interface_id: Bytes,
+
         srcport: Int,
         srcaddr: Ip,
 
@@ -105,40 +465,239 @@ lazy_static::lazy_static! {
         start: Int,
         end: Int,
 
+        protocol: Int,
+        protocol_name: Bytes,
+        packets: Int,
         bytes: Int,
         action: Bytes,
         log_status: Bytes,
+
+        vpc_id: Bytes,
+        subnet_id: Bytes,
+        instance_id: Bytes,
+        tcp_flags: Int,
+        traffic_type: Bytes,
+        pkt_srcaddr: Ip,
+        pkt_dstaddr: Ip,
+
+        region: Bytes,
+        az_id: Bytes,
+        sublocation_type: Bytes,
+        sublocation_id: Bytes,
+
+        pkt_src_aws_service: Bytes,
+        pkt_dst_aws_service: Bytes,
+        flow_direction: Bytes,
+        traffic_path: Int,
     };
 }
 
 impl Searchable for FlowLogLine {
     fn scheme() -> &'static Scheme {
-        &*FLOW_SCHEME
+        &FLOW_SCHEME
+    }
+
+    fn header_fields() -> &'static [&'static str] {
+        FLOW_LOG_FIELDS
     }
 
-    fn execution_context(&self) -> Result<ExecutionContext> {
+    fn time_span(&self) -> Option<(i64, i64)> {
+        Some((self.start?, self.end?))
+    }
+
+    fn execution_context(&self) -> Result<ExecutionContext<'_>> {
         let mut ctx = ExecutionContext::new(Self::scheme());
-        ctx.set_field_value("srcport", self.srcport)
-            .map_err(Report::msg)?;
-        ctx.set_field_value("srcaddr", self.srcaddr)
-            .map_err(Report::msg)?;
 
-        ctx.set_field_value("dstport", self.dstport)
-            .map_err(Report::msg)?;
-        ctx.set_field_value("dstaddr", self.dstaddr)
-            .map_err(Report::msg)?;
+        for (name, value) in &[
+            ("version", self.version.map(i64::from)),
+            ("srcport", self.srcport.map(i64::from)),
+            ("dstport", self.dstport.map(i64::from)),
+            ("protocol", self.protocol.map(i64::from)),
+            ("packets", self.packets),
+            ("bytes", self.bytes),
+            ("start", self.start),
+            ("end", self.end),
+            ("tcp_flags", self.tcp_flags.map(i64::from)),
+            ("traffic_path", self.traffic_path.map(i64::from)),
+        ] {
+            ctx.set_field_value(name, value.map_or(-1, to_filter_int))
+                .map_err(Report::msg)?;
+        }
 
-        ctx.set_field_value("start", self.start)
-            .map_err(Report::msg)?;
-        ctx.set_field_value("end", self.end).map_err(Report::msg)?;
+        for (name, value) in &[
+            ("srcaddr", self.srcaddr),
+            ("dstaddr", self.dstaddr),
+            ("pkt_srcaddr", self.pkt_srcaddr),
+            ("pkt_dstaddr", self.pkt_dstaddr),
+        ] {
+            ctx.set_field_value(name, value.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))
+                .map_err(Report::msg)?;
+        }
 
-        ctx.set_field_value("bytes", self.bytes)
-            .map_err(Report::msg)?;
-        ctx.set_field_value("action", self.action.as_str())
-            .map_err(Report::msg)?;
-        ctx.set_field_value("log_status", self.log_status.as_str())
+        for (name, value) in &[
+            ("account_id", &self.account_id),
+            ("interface_id", &self.interface_id),
+            ("action", &self.action),
+            ("log_status", &self.log_status),
+            ("vpc_id", &self.vpc_id),
+            ("subnet_id", &self.subnet_id),
+            ("instance_id", &self.instance_id),
+            ("traffic_type", &self.traffic_type),
+            ("region", &self.region),
+            ("az_id", &self.az_id),
+            ("sublocation_type", &self.sublocation_type),
+            ("sublocation_id", &self.sublocation_id),
+            ("pkt_src_aws_service", &self.pkt_src_aws_service),
+            ("pkt_dst_aws_service", &self.pkt_dst_aws_service),
+            ("flow_direction", &self.flow_direction),
+        ] {
+            ctx.set_field_value(name, value.as_deref().unwrap_or("-"))
+                .map_err(Report::msg)?;
+        }
+        ctx.set_field_value("protocol_name", self.protocol_name().unwrap_or("-"))
             .map_err(Report::msg)?;
 
         Ok(ctx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{FlowLogLine, RequestLogLine, Searchable, ALB_SCHEME, FLOW_SCHEME};
+
+    const ALB_LINE: &str = r#"http 2018-07-02T22:23:00.186641Z app/my-loadbalancer/50dc6c495c0c9188 192.168.131.39:2817 10.0.0.1:80 0.000 1.501 0.000 502 502 34 366 "GET http://www.example.com:80/users?page=2 HTTP/1.1" "curl/7.46.0" - - arn:aws:elasticloadbalancing:us-east-2:123456789012:targetgroup/my-targets/73e2d6bc24d8a067 "Root=1-58337262-36d228ad5d99923122bbe354" "-" "-" 0 2018-07-02T22:22:48.364000Z "forward" "-" "-" "10.0.0.1:80" "502" "-" "-""#;
+
+    fn parse_alb_line(line: &str) -> RequestLogLine {
+        csv::ReaderBuilder::new()
+            .delimiter(b' ')
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(line.as_bytes())
+            .into_deserialize()
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    fn matches(line: &RequestLogLine, query: &str) -> bool {
+        let filter = ALB_SCHEME.parse(query).unwrap().compile();
+        filter.execute(&line.execution_context().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_deserialize_request_log_line() {
+        let line = parse_alb_line(ALB_LINE);
+        assert_eq!(line.elb_status_code, 502);
+        assert_eq!(line.target_status_code.value(), Some(502));
+        assert_eq!(line.client_port(), Some(2817));
+        assert_eq!(line.target_addr(), Some("10.0.0.1".parse().unwrap()));
+
+        let request = line.request();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.host(), "www.example.com");
+        assert_eq!(request.path(), "/users?page=2");
+        assert_eq!(request.http_version, "HTTP/1.1");
+    }
+
+    #[test]
+    fn test_filter_request_log_line() {
+        let line = parse_alb_line(ALB_LINE);
+        assert!(matches(
+            &line,
+            r#"elb_status_code >= 500 && target_processing_time > 1000 && method == "GET""#
+        ));
+        assert!(matches(&line, r#"host == "www.example.com""#));
+        assert!(!matches(&line, "elb_status_code == 200"));
+    }
+
+    #[test]
+    fn test_missing_target() {
+        let line = parse_alb_line(&ALB_LINE.replacen(
+            " 10.0.0.1:80 0.000 1.501 0.000 502 502",
+            " - -1 -1 -1 503 -",
+            1,
+        ));
+        assert_eq!(line.target_addr(), None);
+        assert_eq!(line.target_status_code.value(), None);
+        assert!(matches(
+            &line,
+            r#"target == "-" && target_status_code == -1 && target_processing_time == -1"#
+        ));
+    }
+
+    #[test]
+    fn test_big_request_log_numbers() {
+        let line = parse_alb_line(&ALB_LINE.replacen(" 34 366 ", " 34 4294967296 ", 1));
+        assert_eq!(line.sent_bytes, 4_294_967_296);
+        assert!(matches(
+            &line,
+            "sent_bytes > 1000000000 && received_bytes == 34"
+        ));
+    }
+
+    const CUSTOM_FLOW_LOG: &str = "version vpc-id subnet-id interface-id srcaddr dstaddr srcport dstport protocol packets bytes start end action log-status tcp-flags type pkt-srcaddr pkt-dstaddr flow-direction traffic-path
+5 vpc-abcd subnet-1234 eni-1235b8ca123456789 10.0.1.5 10.0.0.220 40000 443 6 10 520 1418530010 1418530070 ACCEPT OK 3 IPv4 10.0.1.5 203.0.113.5 egress 1
+5 - - eni-1235b8ca123456789 - - - - - - - 1418530010 1418530070 - NODATA - - - - - -
+";
+
+    fn parse_flow_log(data: &str) -> Vec<FlowLogLine> {
+        csv::ReaderBuilder::new()
+            .delimiter(b' ')
+            .from_reader(data.as_bytes())
+            .into_deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn flow_matches(line: &FlowLogLine, query: &str) -> bool {
+        let filter = FLOW_SCHEME.parse(query).unwrap().compile();
+        filter.execute(&line.execution_context().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_custom_flow_log_format() {
+        let lines = parse_flow_log(CUSTOM_FLOW_LOG);
+        assert_eq!(lines.len(), 2);
+
+        let line = &lines[0];
+        assert_eq!(line.vpc_id.as_deref(), Some("vpc-abcd"));
+        assert_eq!(line.dstport, Some(443));
+        assert_eq!(line.tcp_flags, Some(3));
+        assert_eq!(line.traffic_type.as_deref(), Some("IPv4"));
+        assert_eq!(line.pkt_dstaddr, Some("203.0.113.5".parse().unwrap()));
+        assert_eq!(line.account_id, None);
+        assert!(flow_matches(
+            line,
+            r#"vpc_id == "vpc-abcd" && flow_direction == "egress" && pkt_dstaddr == 203.0.113.5"#
+        ));
+        assert!(flow_matches(line, r#"account_id == "-""#));
+        assert!(flow_matches(
+            line,
+            r#"protocol == 6 && protocol_name == "tcp" && packets == 10 && version == 5"#
+        ));
+
+        let nodata = &lines[1];
+        assert_eq!(nodata.srcaddr, None);
+        assert_eq!(nodata.log_status.as_deref(), Some("NODATA"));
+        assert!(flow_matches(
+            nodata,
+            r#"log_status == "NODATA" && dstport == -1"#
+        ));
+        assert!(!flow_matches(nodata, "dstaddr == 10.0.0.220"));
+    }
+
+    #[test]
+    fn test_big_flow_log_numbers() {
+        let lines = parse_flow_log(
+            "srcaddr dstaddr protocol packets bytes start end
+10.0.0.1 10.0.0.2 17 3000000 4294967296 4102444800 4102444860
+",
+        );
+        let line = &lines[0];
+        assert_eq!(line.bytes, Some(4_294_967_296));
+        assert_eq!(line.start, Some(4_102_444_800));
+        assert_eq!(line.protocol_name(), Some("udp"));
+        assert!(flow_matches(line, "bytes > 1000000000 && packets > 1000"));
+        assert!(flow_matches(line, r#"protocol_name == "udp" && start > 0"#));
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A log line that matched the filter together with where it came from.
+#[derive(Debug, Serialize)]
+pub struct Match<S> {
+    pub key: String,
+    pub line_number: u64,
+    #[serde(flatten)]
+    pub record: S,
+}
+
+/// Destination for matched lines, `finish` is called once after the last match was written.
+pub trait Output<S> {
+    fn write_match(&mut self, matched: &Match<S>) -> Result<()>;
+
+    /// Make the matches written so far visible, called between polls when following new files.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One json object per line, ready to be piped into jq
+    Json,
+    Csv,
+    /// Aligned columns, only printed after all files were processed
+    Table,
+    /// Rust debug formatting
+    #[default]
+    Debug,
+}
+
+impl FromStr for OutputFormat {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        match data {
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "table" => Ok(OutputFormat::Table),
+            "debug" => Ok(OutputFormat::Debug),
+            _ => Err(eyre!(
+                "Invalid output format {:?}, expected one of json, csv, table or debug",
+                data
+            )),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn writer<S, W>(self, writer: W) -> Box<dyn Output<S>>
+    where
+        S: Serialize + Debug,
+        W: Write + 'static,
+    {
+        match self {
+            OutputFormat::Json => Box::new(JsonOutput(writer)),
+            OutputFormat::Csv => Box::new(CsvOutput {
+                writer: csv::Writer::from_writer(writer),
+                wrote_headers: false,
+            }),
+            OutputFormat::Table => Box::new(TableOutput {
+                writer,
+                headers: vec![],
+                rows: vec![],
+            }),
+            OutputFormat::Debug => Box::new(DebugOutput(writer)),
+        }
+    }
+}
+
+pub struct JsonOutput<W>(W);
+
+impl<S: Serialize, W: Write> Output<S> for JsonOutput<W> {
+    fn write_match(&mut self, matched: &Match<S>) -> Result<()> {
+        serde_json::to_writer(&mut self.0, matched).wrap_err("failed to serialize match")?;
+        self.0.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.0.flush()?)
+    }
+}
+
+pub struct CsvOutput<W: Write> {
+    writer: csv::Writer<W>,
+    wrote_headers: bool,
+}
+
+impl<S: Serialize, W: Write> Output<S> for CsvOutput<W> {
+    fn write_match(&mut self, matched: &Match<S>) -> Result<()> {
+        let columns = to_columns(matched)?;
+        if !self.wrote_headers {
+            self.writer
+                .write_record(columns.keys())
+                .wrap_err("failed to write csv headers")?;
+            self.wrote_headers = true;
+        }
+        self.writer
+            .write_record(columns.values().map(to_cell))
+            .wrap_err("failed to write csv record")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+pub struct TableOutput<W> {
+    writer: W,
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl<S: Serialize, W: Write> Output<S> for TableOutput<W> {
+    fn write_match(&mut self, matched: &Match<S>) -> Result<()> {
+        let columns = to_columns(matched)?;
+        if self.headers.is_empty() {
+            self.headers = columns.keys().cloned().collect();
+        }
+        self.rows.push(columns.values().map(to_cell).collect());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        write_table(&mut self.writer, &self.headers, &self.rows)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Write rows as columns aligned to the widest cell, headers included.
+pub(crate) fn write_table<W: Write>(
+    writer: &mut W,
+    headers: &[String],
+    rows: &[Vec<String>],
+) -> Result<()> {
+    let mut widths: Vec<usize> = headers.iter().map(String::len).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    write_table_row(writer, headers, &widths)?;
+    for row in rows {
+        write_table_row(writer, row, &widths)?;
+    }
+    Ok(())
+}
+
+fn write_table_row<W: Write>(writer: &mut W, cells: &[String], widths: &[usize]) -> Result<()> {
+    let line: Vec<_> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect();
+    writeln!(writer, "{}", line.join("  ").trim_end())?;
+    Ok(())
+}
+
+pub struct DebugOutput<W>(W);
+
+impl<S: Debug, W: Write> Output<S> for DebugOutput<W> {
+    fn write_match(&mut self, matched: &Match<S>) -> Result<()> {
+        writeln!(
+            self.0,
+            "Matched {}:{} with {:#?}",
+            matched.key, matched.line_number, matched.record
+        )?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.0.flush()?)
+    }
+}
+
+/// Flatten a match into named columns, keeping the order the fields were declared in.
+fn to_columns<S: Serialize>(matched: &Match<S>) -> Result<Map<String, Value>> {
+    match serde_json::to_value(matched).wrap_err("failed to serialize match")? {
+        Value::Object(columns) => Ok(columns),
+        other => Err(eyre!(
+            "Expected match to serialize to an object, got {}",
+            other
+        )),
+    }
+}
+
+fn to_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use serde::Serialize;
+
+    use super::{Match, OutputFormat};
+
+    #[derive(Debug, Serialize)]
+    struct Record {
+        action: &'static str,
+        bytes: i32,
+    }
+
+    /// Writer that can still be read after being moved into a boxed output.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn render(format: OutputFormat) -> String {
+        let buffer = SharedBuffer::default();
+        let mut output = format.writer::<Record, _>(buffer.clone());
+        let matches = vec![
+            Match {
+                key: "a.log.gz".to_string(),
+                line_number: 2,
+                record: Record {
+                    action: "ACCEPT",
+                    bytes: 40,
+                },
+            },
+            Match {
+                key: "a.log.gz".to_string(),
+                line_number: 10,
+                record: Record {
+                    action: "REJECT",
+                    bytes: 1200,
+                },
+            },
+        ];
+        for matched in &matches {
+            output.write_match(matched).unwrap();
+        }
+        output.finish().unwrap();
+        let data = buffer.0.borrow().clone();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn test_json_output() {
+        assert_eq!(
+            render(OutputFormat::Json),
+            "{\"key\":\"a.log.gz\",\"line_number\":2,\"action\":\"ACCEPT\",\"bytes\":40}\n\
+             {\"key\":\"a.log.gz\",\"line_number\":10,\"action\":\"REJECT\",\"bytes\":1200}\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_output() {
+        assert_eq!(
+            render(OutputFormat::Csv),
+            "key,line_number,action,bytes\na.log.gz,2,ACCEPT,40\na.log.gz,10,REJECT,1200\n"
+        );
+    }
+
+    #[test]
+    fn test_table_output() {
+        assert_eq!(
+            render(OutputFormat::Table),
+            "key       line_number  action  bytes\n\
+             a.log.gz  2            ACCEPT  40\n\
+             a.log.gz  10           REJECT  1200\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_output_format() {
+        assert_eq!("csv".parse::<OutputFormat>().unwrap(), OutputFormat::Csv);
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+
+/// Macros can reference queries which reference other macros, stop before a cycle overflows.
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// Named queries and lists which can be used in filters as `$name`, loaded from a TOML file:
+///
+/// ```toml
+/// [lists]
+/// internal = "10.0.0.0/8 172.16.0.0/12 192.168.0.0/16"
+/// admin_ports = "{22 3389}"
+///
+/// [queries]
+/// rejected-internal = 'dstaddr in $internal && action == "REJECT"'
+/// admin-access = 'dstport in $admin_ports && !$rejected-internal'
+/// ```
+///
+/// Lists are wrapped in braces if they are not already and queries in parentheses, so
+/// `$rejected-internal` above expands to
+/// `(dstaddr in {10.0.0.0/8 172.16.0.0/12 192.168.0.0/16} && action == "REJECT")`.
+#[derive(Debug, Default, Deserialize)]
+pub struct QueryConfig {
+    #[serde(default)]
+    pub lists: BTreeMap<String, String>,
+    #[serde(default)]
+    pub queries: BTreeMap<String, String>,
+}
+
+impl FromStr for QueryConfig {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        Ok(toml::from_str(data)?)
+    }
+}
+
+impl QueryConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        data.parse()
+            .wrap_err_with(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// A saved query with all its macros expanded.
+    pub fn query(&self, name: &str) -> Result<String> {
+        match self.queries.get(name) {
+            Some(query) => self.expand(query),
+            None => Err(eyre!(
+                "Unknown query {:?}, expected one of: {}",
+                name,
+                self.queries
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// Replace every `$name` outside of string literals with the list or query of that name.
+    pub fn expand(&self, query: &str) -> Result<String> {
+        self.expand_with_depth(query, 0)
+    }
+
+    fn expand_with_depth(&self, query: &str, depth: usize) -> Result<String> {
+        if depth > MAX_EXPANSION_DEPTH {
+            return Err(eyre!(
+                "Macros are nested more than {} times, do they reference each other?",
+                MAX_EXPANSION_DEPTH
+            ));
+        }
+
+        let mut expanded = String::with_capacity(query.len());
+        let mut chars = query.char_indices().peekable();
+        let (mut in_string, mut escaped) = (false, false);
+        while let Some((index, c)) = chars.next() {
+            if in_string {
+                expanded.push(c);
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    in_string = false;
+                }
+                continue;
+            }
+            if c != '$' {
+                in_string = c == '"';
+                expanded.push(c);
+                continue;
+            }
+
+            let start = index + c.len_utf8();
+            let mut end = start;
+            while let Some(&(index, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+
+            let name = &query[start..end];
+            if let Some(list) = self.lists.get(name) {
+                let list = list.trim();
+                if list.starts_with('{') {
+                    expanded.push_str(list);
+                } else {
+                    expanded.push_str(&format!("{{{}}}", list));
+                }
+            } else if let Some(macro_query) = self.queries.get(name) {
+                let macro_query = self.expand_with_depth(macro_query, depth + 1)?;
+                expanded.push_str(&format!("({})", macro_query));
+            } else {
+                return Err(eyre!(
+                    "Unknown macro ${} ({}):\n{}\n{}{}",
+                    name,
+                    index + 1,
+                    query,
+                    " ".repeat(query[..index].chars().count()),
+                    "^".repeat(name.chars().count() + 1)
+                ));
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryConfig;
+
+    const CONFIG: &str = r#"
+[lists]
+internal = "10.0.0.0/8 172.16.0.0/12"
+admin_ports = "{22 3389}"
+
+[queries]
+rejected-internal = 'dstaddr in $internal && action == "REJECT"'
+admin-access = 'dstport in $admin_ports && !$rejected-internal'
+loop = '$loop'
+"#;
+
+    #[test]
+    fn test_expand_queries() {
+        let config: QueryConfig = CONFIG.parse().unwrap();
+        assert_eq!(
+            config.query("rejected-internal").unwrap(),
+            r#"dstaddr in {10.0.0.0/8 172.16.0.0/12} && action == "REJECT""#
+        );
+        assert_eq!(
+            config.query("admin-access").unwrap(),
+            r#"dstport in {22 3389} && !(dstaddr in {10.0.0.0/8 172.16.0.0/12} && action == "REJECT")"#
+        );
+        assert_eq!(
+            config
+                .expand(r#"action == "$internal" && srcaddr in $internal"#)
+                .unwrap(),
+            r#"action == "$internal" && srcaddr in {10.0.0.0/8 172.16.0.0/12}"#
+        );
+
+        assert!(config.query("missing").is_err());
+        assert!(config.query("loop").is_err());
+    }
+
+    #[test]
+    fn test_unknown_macro_position() {
+        let config: QueryConfig = CONFIG.parse().unwrap();
+        let error = config.expand("dstport in $ports").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown macro $ports (12):\ndstport in $ports\n           ^^^^^^"
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use aws_sdk_s3::Client;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Result, WrapErr};
+use futures::executor::{block_on, block_on_stream};
+use log::{debug, warn};
+
+use aws_cli_common::format_size;
+use aws_s3_utils::{download_object, BucketKeyIterator, KeyFilter, RetryPolicy};
+
+/// Key used for logs coming through stdin.
+pub const STDIN_KEY: &str = "-";
+
+/// Where log files are read from.
+pub enum Source<'a> {
+    S3 {
+        client: &'a Client,
+        bucket: &'a str,
+        prefixes: Vec<String>,
+        retry: RetryPolicy,
+    },
+    /// A single file or a directory which is read recursively
+    Path(PathBuf),
+    Glob(String),
+    Stdin,
+}
+
+impl<'a> Source<'a> {
+    pub fn s3(client: &'a Client, bucket: &'a str, prefix: &'a str) -> Self {
+        Self::s3_prefixes(client, bucket, vec![prefix.to_owned()])
+    }
+
+    /// List several prefixes of the same bucket, e.g. one per day from `TimeRange::daily_prefixes`.
+    pub fn s3_prefixes(client: &'a Client, bucket: &'a str, prefixes: Vec<String>) -> Self {
+        Source::S3 {
+            client,
+            bucket,
+            prefixes,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// How failed S3 requests are retried, local sources ignore it.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        if let Source::S3 { retry, .. } = &mut self {
+            *retry = policy;
+        }
+        self
+    }
+
+    /// Local input as given on the command line: `-` for stdin, a glob pattern, a file or a
+    /// directory. An existing file is read as is even when its name looks like a pattern, e.g.
+    /// `logs[1].log`.
+    pub fn local(input: &str) -> Self {
+        if input == STDIN_KEY {
+            Source::Stdin
+        } else if input.contains(['*', '?', '[']) && !Path::new(input).exists() {
+            Source::Glob(input.to_owned())
+        } else {
+            Source::Path(PathBuf::from(input))
+        }
+    }
+
+    /// List all log files, these can then be passed to `open`. Archived S3 objects are skipped as
+    /// they cannot be downloaded without restoring them first.
+    pub(crate) fn files(&self) -> Result<Vec<LogFile>> {
+        let paths = match self {
+            Source::S3 {
+                client,
+                bucket,
+                prefixes,
+                retry,
+            } => {
+                let mut files = vec![];
+                for prefix in prefixes {
+                    files.extend(list_s3_files(client, bucket, prefix, retry)?);
+                }
+                return Ok(files);
+            }
+            Source::Stdin => {
+                return Ok(vec![LogFile {
+                    key: STDIN_KEY.to_owned(),
+                    size: None,
+                    last_modified: None,
+                }])
+            }
+            Source::Path(path) if path.is_dir() => {
+                let mut paths = vec![];
+                list_files(path, &mut paths)?;
+                paths
+            }
+            Source::Path(path) => vec![path.clone()],
+            Source::Glob(pattern) => {
+                let mut paths = vec![];
+                for entry in glob::glob(pattern).wrap_err("invalid glob pattern")? {
+                    let entry = entry?;
+                    if entry.is_file() {
+                        paths.push(entry);
+                    }
+                }
+                paths
+            }
+        };
+
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let metadata = fs::metadata(&path).ok();
+                LogFile {
+                    size: metadata.as_ref().map(|metadata| metadata.len()),
+                    last_modified: metadata
+                        .and_then(|metadata| metadata.modified().ok())
+                        .map(DateTime::<Utc>::from),
+                    key: path.to_string_lossy().into_owned(),
+                }
+            })
+            .collect())
+    }
+
+    /// Open a log file, the content is returned as is so it may still be compressed.
+    pub(crate) fn open(&self, file: &LogFile) -> Result<Option<Box<dyn Read + 'a>>> {
+        match self {
+            Source::S3 {
+                client,
+                bucket,
+                retry,
+                ..
+            } => open_s3_file(client, bucket, file, retry),
+            Source::Path(_) | Source::Glob(_) => {
+                let key = &file.key;
+                let file =
+                    File::open(key).wrap_err_with(|| format!("failed to open log file {}", key))?;
+                Ok(Some(Box::new(file)))
+            }
+            Source::Stdin => Ok(Some(Box::new(io::stdin()))),
+        }
+    }
+}
+
+/// A log file to be processed, the size and modification time are known upfront for everything
+/// but stdin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFile {
+    pub key: String,
+    pub size: Option<u64>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+fn list_s3_files(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+    retry: &RetryPolicy,
+) -> Result<Vec<LogFile>> {
+    let mut files = vec![];
+    // Folder placeholders created by the console are the only keys that are never logs
+    let filter = KeyFilter::none().exclude_suffix("/");
+    let iterator = BucketKeyIterator::new(bucket, Some(prefix), client)
+        .with_filter(filter)
+        .with_retry(retry.clone());
+    for object in block_on_stream(iterator) {
+        let object = object?;
+        if object.is_archived() {
+            warn!(
+                "Skipping s3://{}/{} because it is archived in {}",
+                bucket,
+                object.key,
+                object.storage_class.unwrap_or_default()
+            );
+            continue;
+        }
+        files.push(LogFile {
+            key: object.key,
+            size: Some(object.size),
+            last_modified: object.last_modified,
+        });
+    }
+    Ok(files)
+}
+
+/// Recursively collect all files under `path` in a stable order.
+fn list_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(path)
+        .wrap_err_with(|| format!("failed to list {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            list_files(&entry, files)?;
+        } else {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// The whole file is downloaded before it is read, so a connection which breaks halfway can be
+/// retried from the start without the lines read so far being processed twice.
+fn open_s3_file(
+    client: &Client,
+    bucket: &str,
+    file: &LogFile,
+    retry: &RetryPolicy,
+) -> Result<Option<Box<dyn Read>>> {
+    let key = &file.key;
+    debug!("Starting to download from s3://{}/{}", bucket, key);
+    let data = block_on(download_object(client, bucket, key, retry))
+        .wrap_err("Error downloading log file")?;
+    debug!(
+        "Processing bucket: {} key: {} size: {}",
+        bucket,
+        key,
+        format_size(data.len() as u64)
+    );
+    Ok(Some(Box::new(Cursor::new(data))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, File};
+
+    use super::{Source, STDIN_KEY};
+
+    fn collect(source: &Source) -> Vec<String> {
+        let files = source.files().unwrap();
+        files.into_iter().map(|file| file.key).collect()
+    }
+
+    #[test]
+    fn test_local_source() {
+        assert!(matches!(Source::local("-"), Source::Stdin));
+        assert!(matches!(Source::local("logs/*.gz"), Source::Glob(_)));
+        assert!(matches!(Source::local("logs/"), Source::Path(_)));
+    }
+
+    #[test]
+    fn test_list_local_keys() {
+        let directory = tempfile::tempdir().unwrap();
+        create_dir(directory.path().join("2024")).unwrap();
+        for name in &["b.log.gz", "a.log", "2024/c.log.gz"] {
+            File::create(directory.path().join(name)).unwrap();
+        }
+        let path = |parts: &[&str]| {
+            let path = parts
+                .iter()
+                .fold(directory.path().to_owned(), |path, part| path.join(part));
+            path.to_string_lossy().into_owned()
+        };
+        let root = path(&[]);
+
+        let keys = collect(&Source::local(&root));
+        assert_eq!(
+            keys,
+            vec![
+                path(&["2024", "c.log.gz"]),
+                path(&["a.log"]),
+                path(&["b.log.gz"])
+            ]
+        );
+
+        let keys = collect(&Source::local(&path(&["*.gz"])));
+        assert_eq!(keys, vec![path(&["b.log.gz"])]);
+
+        File::create(directory.path().join("logs[1].log")).unwrap();
+        let keys = collect(&Source::local(&path(&["logs[1].log"])));
+        assert_eq!(keys, vec![path(&["logs[1].log"])]);
+
+        assert_eq!(collect(&Source::Stdin), vec![STDIN_KEY.to_owned()]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+/// How many error messages are kept to show what went wrong, the rest is only counted.
+const MAX_ERROR_SAMPLES: usize = 5;
+
+/// Line counts of one or more log files.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub files: u64,
+    pub parsed: u64,
+    pub matched: u64,
+    pub failed: u64,
+    /// The first few parse errors as `key:line: error`
+    pub errors: Vec<String>,
+}
+
+impl Stats {
+    pub(crate) fn add_error(&mut self, error: String) {
+        self.failed += 1;
+        if self.errors.len() < MAX_ERROR_SAMPLES {
+            self.errors.push(error);
+        }
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.files += other.files;
+        self.parsed += other.parsed;
+        self.matched += other.matched;
+        self.failed += other.failed;
+        let room = MAX_ERROR_SAMPLES.saturating_sub(self.errors.len());
+        self.errors.extend(other.errors.iter().take(room).cloned());
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Scanned {} files: {} lines parsed, {} matched, {} failed",
+            self.files, self.parsed, self.matched, self.failed
+        )?;
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+        if self.failed > self.errors.len() as u64 {
+            write!(
+                f,
+                "\n  ... and {} more",
+                self.failed - self.errors.len() as u64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+
+    #[test]
+    fn test_merge_stats() {
+        let mut total = Stats::default();
+        for file in 0..3 {
+            let mut stats = Stats {
+                files: 1,
+                parsed: 10,
+                matched: 2,
+                ..Default::default()
+            };
+            for line in 0..3 {
+                stats.add_error(format!("{}.log:{}: invalid digit", file, line));
+            }
+            total.merge(&stats);
+        }
+
+        assert_eq!(total.files, 3);
+        assert_eq!(total.parsed, 30);
+        assert_eq!(total.failed, 9);
+        assert_eq!(total.errors.len(), 5);
+        assert_eq!(total.to_string().lines().last(), Some("  ... and 4 more"));
+    }
+}
@@ -0,0 +1,230 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use color_eyre::eyre::{eyre, Result};
+
+/// Log files are named after the time they were delivered, but they can contain records from a
+/// few minutes before that, or be delivered a few minutes late.
+const FILE_TIME_MARGIN_MINUTES: i64 = 30;
+
+/// A point in time given on the command line, either absolute like `2024-03-01T10:00:00Z`,
+/// `2024-03-01 10:00` or `2024-03-01`, or relative to now like `30m`, `2h` or `7d`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSpec {
+    Absolute(DateTime<Utc>),
+    Ago(Duration),
+}
+
+impl TimeSpec {
+    pub fn resolve(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            TimeSpec::Absolute(time) => *time,
+            TimeSpec::Ago(duration) => now - *duration,
+        }
+    }
+}
+
+impl FromStr for TimeSpec {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(data) {
+            return Ok(TimeSpec::Absolute(time.with_timezone(&Utc)));
+        }
+        for format in &[
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%dT%H:%M",
+            "%Y-%m-%d %H:%M",
+        ] {
+            if let Ok(time) = NaiveDateTime::parse_from_str(data, format) {
+                return Ok(TimeSpec::Absolute(Utc.from_utc_datetime(&time)));
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(data, "%Y-%m-%d") {
+            let time = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+            return Ok(TimeSpec::Absolute(Utc.from_utc_datetime(&time)));
+        }
+
+        let unit_index = data.char_indices().last().map_or(0, |(index, _)| index);
+        let (amount, unit) = (&data[..unit_index], &data[unit_index..]);
+        let amount: i64 = amount
+            .parse()
+            .map_err(|_| eyre!("Invalid time {:?}, use a date or something like 2h", data))?;
+        let duration = match unit {
+            "s" => Duration::seconds(amount),
+            "m" => Duration::minutes(amount),
+            "h" => Duration::hours(amount),
+            "d" => Duration::days(amount),
+            "w" => Duration::weeks(amount),
+            _ => {
+                return Err(eyre!(
+                    "Invalid time unit {:?}, expected s, m, h, d or w",
+                    unit
+                ))
+            }
+        };
+        Ok(TimeSpec::Ago(duration))
+    }
+}
+
+/// Window of time to scan, either end can be left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeRange {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    pub fn new(since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> Self {
+        Self { since, until }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Whether a record covering `start` to `end`, in unix seconds, overlaps with the range.
+    pub fn overlaps(&self, start: i64, end: i64) -> bool {
+        self.since.is_none_or(|since| end >= since.timestamp())
+            && self.until.is_none_or(|until| start <= until.timestamp())
+    }
+
+    /// Use the timestamp in the file name, like in
+    /// `123456789012_vpcflowlogs_eu-central-1_fl-1234_20240301T1005Z_abcd.log.gz`, to skip files
+    /// which cannot have records in the range. Files without a timestamp are always kept.
+    pub fn may_contain_file(&self, key: &str) -> bool {
+        let margin = Duration::minutes(FILE_TIME_MARGIN_MINUTES);
+        match file_timestamp(key) {
+            Some(time) => {
+                self.since.is_none_or(|since| time >= since - margin)
+                    && self.until.is_none_or(|until| time <= until + margin)
+            }
+            None => true,
+        }
+    }
+
+    /// Expand `base`, which should point to `AWSLogs/<account>/vpcflowlogs/<region>/`, into one
+    /// prefix per day of the range. Without a start only `base` is returned.
+    pub fn daily_prefixes(&self, base: &str) -> Vec<String> {
+        let since = match self.since {
+            Some(since) => since,
+            None => return vec![base.to_owned()],
+        };
+        let margin = Duration::minutes(FILE_TIME_MARGIN_MINUTES);
+        let base = if base.is_empty() || base.ends_with('/') {
+            base.to_owned()
+        } else {
+            format!("{}/", base)
+        };
+
+        let last_day = (self.until.unwrap_or_else(Utc::now) + margin).date_naive();
+        let mut day = (since - margin).date_naive();
+        let mut prefixes = vec![];
+        while day <= last_day {
+            prefixes.push(format!("{}{}/", base, day.format("%Y/%m/%d")));
+            day = match day.succ_opt() {
+                Some(day) => day,
+                None => break,
+            };
+        }
+        prefixes
+    }
+}
+
+/// Find the `YYYYMMDDTHHmmZ` part of a log file name.
+fn file_timestamp(key: &str) -> Option<DateTime<Utc>> {
+    let name = key.rsplit(['/', '\\']).next()?;
+    name.split('_').find_map(|part| {
+        let bytes = part.as_bytes();
+        if bytes.len() != 14 || bytes[8] != b'T' || bytes[13] != b'Z' {
+            return None;
+        }
+        let number = |range: std::ops::Range<usize>| part.get(range)?.parse::<u32>().ok();
+        let time = NaiveDate::from_ymd_opt(number(0..4)? as i32, number(4..6)?, number(6..8)?)?
+            .and_hms_opt(number(9..11)?, number(11..13)?, 0)?;
+        Some(Utc.from_utc_datetime(&time))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{file_timestamp, TimeRange, TimeSpec};
+
+    #[test]
+    fn test_parse_time_spec() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let parse = |data: &str| data.parse::<TimeSpec>().unwrap().resolve(now);
+
+        assert_eq!(
+            parse("2h"),
+            Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse("1d"),
+            Utc.with_ymd_and_hms(2024, 2, 29, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse("2024-02-01T10:30:00Z"),
+            Utc.with_ymd_and_hms(2024, 2, 1, 10, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse("2024-02-01 10:30"),
+            Utc.with_ymd_and_hms(2024, 2, 1, 10, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse("2024-02-01"),
+            Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap()
+        );
+
+        assert!("2y".parse::<TimeSpec>().is_err());
+        assert!("yesterday".parse::<TimeSpec>().is_err());
+        assert!("".parse::<TimeSpec>().is_err());
+    }
+
+    #[test]
+    fn test_daily_prefixes() {
+        let range = TimeRange::new(
+            Some(Utc.with_ymd_and_hms(2024, 2, 28, 10, 0, 0).unwrap()),
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 23, 50, 0).unwrap()),
+        );
+        assert_eq!(
+            range.daily_prefixes("AWSLogs/1234/vpcflowlogs/eu-central-1"),
+            vec![
+                "AWSLogs/1234/vpcflowlogs/eu-central-1/2024/02/28/",
+                "AWSLogs/1234/vpcflowlogs/eu-central-1/2024/02/29/",
+                "AWSLogs/1234/vpcflowlogs/eu-central-1/2024/03/01/",
+                // Late deliveries for the last minutes of the range
+                "AWSLogs/1234/vpcflowlogs/eu-central-1/2024/03/02/",
+            ]
+        );
+        assert_eq!(TimeRange::default().daily_prefixes("logs/"), vec!["logs/"]);
+    }
+
+    #[test]
+    fn test_may_contain_file() {
+        let key = "AWSLogs/1234/vpcflowlogs/eu-central-1/2024/03/01/1234_vpcflowlogs_eu-central-1_fl-1234_20240301T1005Z_abcd.log.gz";
+        let time = file_timestamp(key).unwrap();
+        assert_eq!(time, Utc.with_ymd_and_hms(2024, 3, 1, 10, 5, 0).unwrap());
+
+        assert!(TimeRange::new(Some(time), None).may_contain_file(key));
+        assert!(TimeRange::new(Some(time + Duration::minutes(20)), None).may_contain_file(key));
+        assert!(!TimeRange::new(Some(time + Duration::hours(2)), None).may_contain_file(key));
+        assert!(!TimeRange::new(None, Some(time - Duration::hours(2))).may_contain_file(key));
+        assert!(TimeRange::new(Some(time + Duration::hours(2)), None).may_contain_file("a.log"));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let range = TimeRange::new(
+            Some(Utc.timestamp_opt(1000, 0).unwrap()),
+            Some(Utc.timestamp_opt(2000, 0).unwrap()),
+        );
+        assert!(range.overlaps(900, 1100));
+        assert!(range.overlaps(1900, 2100));
+        assert!(!range.overlaps(100, 999));
+        assert!(!range.overlaps(2001, 2100));
+    }
+}
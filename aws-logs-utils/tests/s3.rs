@@ -0,0 +1,144 @@
+use std::io::Write;
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tokio::runtime::Runtime;
+
+use aws_logs_utils::log_types::FlowLogLine;
+use aws_logs_utils::output::{Match, Output};
+use aws_logs_utils::source::Source;
+use aws_logs_utils::Parser;
+use aws_s3_utils::RetryPolicy;
+use s3_mock::{Failure, MockS3};
+
+const BUCKET: &str = "flow-logs";
+const PREFIX: &str = "AWSLogs/123456789012/vpcflowlogs/eu-central-1/2024/03/01/";
+const HEADER: &str = "version account-id interface-id srcaddr dstaddr srcport dstport protocol packets bytes start end action log-status";
+const QUERY: &str = r#"action == "REJECT" && dstport == 22"#;
+
+#[derive(Default)]
+struct Collect(Vec<(String, u64, String)>);
+
+impl Output<FlowLogLine> for Collect {
+    fn write_match(&mut self, matched: &Match<FlowLogLine>) -> Result<()> {
+        let srcaddr = matched.record.srcaddr.unwrap().to_string();
+        self.0
+            .push((matched.key.clone(), matched.line_number, srcaddr));
+        Ok(())
+    }
+}
+
+fn key(file: usize) -> String {
+    format!(
+        "{}123456789012_vpcflowlogs_eu-central-1_fl-1234_20240301T10{:02}Z_abcd.log.gz",
+        PREFIX,
+        file * 5
+    )
+}
+
+/// 20 records per file, every sixth one is a rejected ssh connection.
+fn flow_log(file: usize) -> Vec<u8> {
+    let mut log = format!("{}\n", HEADER);
+    for line in 0..20 {
+        let dstport = if line % 3 == 0 { 22 } else { 443 };
+        let action = if line % 2 == 0 { "REJECT" } else { "ACCEPT" };
+        log.push_str(&format!(
+            "2 123456789012 eni-1 10.0.{}.{} 10.1.0.1 40000 {} 6 10 840 1709287200 1709287260 {} OK\n",
+            file, line, dstport, action
+        ));
+    }
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(log.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn expected(files: usize) -> Vec<(String, u64, String)> {
+    let mut expected = vec![];
+    for file in 0..files {
+        for line in &[0, 6, 12, 18] {
+            // The header is the first line
+            expected.push((key(file), line + 2, format!("10.0.{}.{}", file, line)));
+        }
+    }
+    expected
+}
+
+fn mock_with_logs(files: usize) -> MockS3 {
+    let s3 = MockS3::start();
+    for file in 0..files {
+        s3.put_object(BUCKET, &key(file), flow_log(file));
+    }
+    s3.put_object(BUCKET, "AWSLogs/123456789012/other.log.gz", flow_log(99));
+    s3
+}
+
+#[test]
+fn test_scan_bucket_across_pages() {
+    let runtime = Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let s3 = mock_with_logs(5);
+    s3.set_page_size(2);
+    let client = s3.client();
+
+    let parser = Parser::<FlowLogLine>::new(&client, BUCKET, PREFIX, QUERY)
+        .unwrap()
+        .with_concurrency(3);
+    let mut output = Collect::default();
+    let stats = parser.parse_logs_with_output(&mut output).unwrap();
+
+    assert_eq!(output.0, expected(5));
+    assert_eq!(
+        (stats.files, stats.parsed, stats.matched, stats.failed),
+        (5, 100, 20, 0)
+    );
+    let listings = s3
+        .requests()
+        .iter()
+        .filter(|request| request.query.contains_key("list-type"))
+        .count();
+    assert_eq!(listings, 3);
+}
+
+#[test]
+fn test_scan_with_failures() {
+    let runtime = Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let s3 = mock_with_logs(3);
+    let client = s3.client();
+    let retry = RetryPolicy {
+        base_delay: Duration::from_millis(1),
+        throttling_delay: Duration::from_millis(1),
+        ..Default::default()
+    };
+
+    // Throttled listing, then a download which breaks halfway and is not processed twice
+    s3.fail_next(&[Failure::Status(503), Failure::BrokenBody]);
+    let source = Source::s3(&client, BUCKET, PREFIX).with_retry(retry);
+    let parser = Parser::<FlowLogLine>::from_source(source, QUERY).unwrap();
+    let mut output = Collect::default();
+    let stats = parser.parse_logs_with_output(&mut output).unwrap();
+    assert_eq!(output.0, expected(3));
+    assert_eq!(stats.parsed, 60);
+
+    let missing = Parser::<FlowLogLine>::new(&client, "missing", PREFIX, QUERY).unwrap();
+    assert!(missing
+        .parse_logs_with_output(&mut Collect::default())
+        .is_err());
+}
+
+#[test]
+fn test_empty_prefix() {
+    let runtime = Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let s3 = MockS3::start();
+    s3.create_bucket(BUCKET);
+    let client = s3.client();
+
+    let parser = Parser::<FlowLogLine>::new(&client, BUCKET, PREFIX, QUERY).unwrap();
+    let mut output = Collect::default();
+    let stats = parser.parse_logs_with_output(&mut output).unwrap();
+    assert!(output.0.is_empty());
+    assert_eq!(stats.files, 0);
+}
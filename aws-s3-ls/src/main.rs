@@ -0,0 +1,188 @@
+use aws_sdk_s3::Client;
+use clap::Parser as ClapParser;
+use color_eyre::eyre::{Result, WrapErr};
+use futures::future::{BoxFuture, FutureExt};
+
+use aws_cli_common::{format_size, AwsOptions};
+use aws_s3_utils::{list_children, RetryPolicy};
+
+/// Show the prefixes of a bucket as a tree, to find the one to give to aws-flow-logs or
+/// aws-alb-logs.
+#[derive(Debug, ClapParser)]
+pub struct Options {
+    pub bucket: String,
+    /// Where to start, e.g. AWSLogs/123456789012/, by default the root of the bucket
+    #[arg(default_value = "", hide_default_value = true)]
+    pub prefix: String,
+    /// How many levels of prefixes to list below PREFIX
+    #[arg(short = 'd', long, default_value_t = 2)]
+    pub depth: usize,
+    /// Show the objects with their size too, not only the prefixes
+    #[arg(short = 'o', long)]
+    pub objects: bool,
+    /// Split keys into levels on this instead of /
+    #[arg(long, default_value = aws_s3_utils::DEFAULT_DELIMITER)]
+    pub delimiter: String,
+    #[command(flatten)]
+    pub aws: AwsOptions,
+}
+
+aws_cli_common::options!(Options);
+
+/// A prefix or an object in the tree, named relative to its parent.
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    name: String,
+    /// Only objects have a size
+    size: Option<u64>,
+    children: Vec<Node>,
+}
+
+/// Lists a bucket one level at a time, so only the prefixes shown are ever listed.
+struct Tree<'a> {
+    client: &'a Client,
+    bucket: &'a str,
+    delimiter: &'a str,
+    objects: bool,
+    retry: RetryPolicy,
+}
+
+impl<'a> Tree<'a> {
+    fn list(&'a self, prefix: String, depth: usize) -> BoxFuture<'a, Result<Vec<Node>>> {
+        async move {
+            let children = list_children(
+                self.client,
+                self.bucket,
+                &prefix,
+                self.delimiter,
+                &self.retry,
+            )
+            .await
+            .wrap_err_with(|| format!("failed to list s3://{}/{}", self.bucket, prefix))?;
+
+            let mut nodes = vec![];
+            for child in children.prefixes {
+                let grandchildren = if depth > 1 {
+                    self.list(child.clone(), depth - 1).await?
+                } else {
+                    vec![]
+                };
+                nodes.push(Node {
+                    name: child[prefix.len()..].to_owned(),
+                    size: None,
+                    children: grandchildren,
+                });
+            }
+            if self.objects {
+                // The placeholder of a folder created in the console is the prefix itself
+                for object in children.objects.into_iter().filter(|o| o.key != prefix) {
+                    nodes.push(Node {
+                        name: object.key[prefix.len()..].to_owned(),
+                        size: Some(object.size),
+                        children: vec![],
+                    });
+                }
+            }
+            Ok(nodes)
+        }
+        .boxed()
+    }
+}
+
+/// Draw the nodes like tree(1), one line each.
+fn render(nodes: &[Node], indent: &str, lines: &mut Vec<String>) {
+    for (index, node) in nodes.iter().enumerate() {
+        let (branch, next_indent) = if index + 1 == nodes.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let label = match node.size {
+            Some(size) => format!("{} ({})", node.name, format_size(size)),
+            None => node.name.clone(),
+        };
+        lines.push(format!("{}{}{}", indent, branch, label));
+        render(&node.children, &format!("{}{}", indent, next_indent), lines);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    aws_cli_common::init()?;
+    let client = OPTIONS.aws.client(aws_s3_utils::client).await;
+    let tree = Tree {
+        client: &client,
+        bucket: &OPTIONS.bucket,
+        delimiter: &OPTIONS.delimiter,
+        objects: OPTIONS.objects,
+        retry: OPTIONS.aws.retry_policy(),
+    };
+    let nodes = tree
+        .list(OPTIONS.prefix.clone(), OPTIONS.depth.max(1))
+        .await?;
+
+    let mut lines = vec![format!("s3://{}/{}", OPTIONS.bucket, OPTIONS.prefix)];
+    render(&nodes, "", &mut lines);
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_s3_utils::RetryPolicy;
+    use s3_mock::MockS3;
+
+    use super::{render, Tree};
+
+    #[tokio::test]
+    async fn test_list_tree() {
+        let s3 = MockS3::start();
+        for key in &[
+            "AWSLogs/111111111111/vpcflowlogs/eu-central-1/2024/03/01/a.log.gz",
+            "AWSLogs/111111111111/vpcflowlogs/eu-west-1/2024/03/01/b.log.gz",
+            "AWSLogs/111111111111/elasticloadbalancing/eu-central-1/2024/03/01/c.log.gz",
+            "AWSLogs/222222222222/vpcflowlogs/us-east-1/2024/03/01/d.log.gz",
+            "AWSLogs/",
+            "AWSLogs/readme.txt",
+        ] {
+            s3.put_object("logs", key, vec![0u8; 2048]);
+        }
+        let client = s3.client();
+        let mut tree = Tree {
+            client: &client,
+            bucket: "logs",
+            delimiter: "/",
+            objects: false,
+            retry: RetryPolicy::none(),
+        };
+
+        let nodes = tree.list("AWSLogs/".to_owned(), 2).await.unwrap();
+        let mut lines = vec![];
+        render(&nodes, "", &mut lines);
+        assert_eq!(
+            lines,
+            vec![
+                "├── 111111111111/",
+                "│   ├── elasticloadbalancing/",
+                "│   └── vpcflowlogs/",
+                "└── 222222222222/",
+                "    └── vpcflowlogs/",
+            ]
+        );
+
+        tree.objects = true;
+        let nodes = tree.list("AWSLogs/".to_owned(), 1).await.unwrap();
+        let mut lines = vec![];
+        render(&nodes, "", &mut lines);
+        assert_eq!(
+            lines,
+            vec![
+                "├── 111111111111/",
+                "├── 222222222222/",
+                "└── readme.txt (2.00 KiB)",
+            ]
+        );
+    }
+}
@@ -1,4 +1,9 @@
+use std::error::Error as StdError;
 use std::result::Result as StdResult;
+
+use aws_cli_common::retry::{ErrorKind, Retryable};
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
 use thiserror::Error as ThisError;
 
 pub type Result<T> = StdResult<T, Error>;
@@ -8,7 +13,48 @@ pub enum Error {
     #[error("Key was not present")]
     KeyNotPresent,
 
-    // Use eyre just to easily wrap rusoto as it has typed errors
+    #[error("Invalid glob pattern: {0}")]
+    InvalidGlob(#[from] glob::PatternError),
+
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(#[from] regex::Error),
+
+    /// A failed S3 request, `kind` tells whether it is worth retrying
+    #[error("S3 request failed ({kind:?}): {source}")]
+    Request {
+        kind: ErrorKind,
+        source: eyre::Error,
+    },
+
+    // Use eyre just to easily wrap the SDK as it has typed errors
     #[error(transparent)]
-    RusotoError(#[from] eyre::Error),
+    Other(#[from] eyre::Error),
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Request { kind, .. } => *kind,
+            _ => ErrorKind::Permanent,
+        }
+    }
+}
+
+impl Retryable for Error {
+    fn kind(&self) -> ErrorKind {
+        Error::kind(self)
+    }
+}
+
+impl<E> From<SdkError<E, HttpResponse>> for Error
+where
+    E: ProvideErrorMetadata + StdError + Send + Sync + 'static,
+{
+    fn from(error: SdkError<E, HttpResponse>) -> Self {
+        Error::Request {
+            kind: error.kind(),
+            // The SDK error only displays its variant, the cause is in its sources
+            source: eyre::eyre!("{}", DisplayErrorContext(&error)),
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use regex::Regex;
+
+use crate::error::Result;
+
+/// A rule matched against object keys.
+#[derive(Debug, Clone)]
+pub enum KeyPattern {
+    Suffix(String),
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl KeyPattern {
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            KeyPattern::Suffix(suffix) => key.ends_with(suffix.as_str()),
+            KeyPattern::Glob(pattern) => pattern.matches(key),
+            KeyPattern::Regex(regex) => regex.is_match(key),
+        }
+    }
+}
+
+/// Decide which keys are returned by a listing. A key is kept when it matches any of the include
+/// rules, or when there are none, and does not match any of the exclude rules.
+///
+/// ```rust
+/// use aws_s3_utils::KeyFilter;
+///
+/// let filter = KeyFilter::none()
+///     .include_suffix(".gz")
+///     .exclude_glob("*/tmp/*")
+///     .unwrap();
+/// assert!(filter.matches("logs/2024/a.log.gz"));
+/// assert!(!filter.matches("logs/tmp/a.log.gz"));
+/// assert!(!filter.matches("logs/2024/a.parquet"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyFilter {
+    include: Vec<KeyPattern>,
+    exclude: Vec<KeyPattern>,
+}
+
+impl KeyFilter {
+    /// Filter that accepts every key.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn include(mut self, pattern: KeyPattern) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    pub fn exclude(mut self, pattern: KeyPattern) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    pub fn include_suffix(self, suffix: &str) -> Self {
+        self.include(KeyPattern::Suffix(suffix.to_owned()))
+    }
+
+    pub fn exclude_suffix(self, suffix: &str) -> Self {
+        self.exclude(KeyPattern::Suffix(suffix.to_owned()))
+    }
+
+    pub fn include_glob(self, pattern: &str) -> Result<Self> {
+        Ok(self.include(KeyPattern::Glob(glob::Pattern::new(pattern)?)))
+    }
+
+    pub fn exclude_glob(self, pattern: &str) -> Result<Self> {
+        Ok(self.exclude(KeyPattern::Glob(glob::Pattern::new(pattern)?)))
+    }
+
+    pub fn include_regex(self, regex: &str) -> Result<Self> {
+        Ok(self.include(KeyPattern::Regex(Regex::new(regex)?)))
+    }
+
+    pub fn exclude_regex(self, regex: &str) -> Result<Self> {
+        Ok(self.exclude(KeyPattern::Regex(Regex::new(regex)?)))
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|p| p.matches(key));
+        included && !self.exclude.iter().any(|p| p.matches(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyFilter;
+
+    #[test]
+    fn test_no_filter() {
+        let filter = KeyFilter::none();
+        assert!(filter.matches("a.log"));
+        assert!(filter.matches(""));
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let filter = KeyFilter::none()
+            .include_suffix(".gz")
+            .include_regex(r"\.parquet$")
+            .unwrap()
+            .exclude_glob("archive/*")
+            .unwrap();
+
+        assert!(filter.matches("logs/a.log.gz"));
+        assert!(filter.matches("logs/a.parquet"));
+        assert!(!filter.matches("logs/a.log"));
+        assert!(!filter.matches("archive/a.log.gz"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(KeyFilter::none().include_glob("[").is_err());
+        assert!(KeyFilter::none().exclude_regex("(").is_err());
+    }
+}
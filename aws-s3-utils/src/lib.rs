@@ -1,75 +1,241 @@
-use rusoto_s3::{S3Client, S3};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
+use std::result::Result as StdResult;
+use std::task::{Context, Poll};
 
-use crate::error::{Error, Result};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::list_objects_v2::{ListObjectsV2Error, ListObjectsV2Output};
+use aws_sdk_s3::{Client, Config};
+use aws_smithy_async::future::pagination_stream::PaginationStream;
+use aws_types::SdkConfig;
+use futures::{Stream, StreamExt};
+use log::warn;
+use tokio::time::Sleep;
+
+pub use crate::error::{Error, Result};
+pub use crate::filter::{KeyFilter, KeyPattern};
+pub use crate::object::BucketObject;
+pub use crate::prefix::{list_child_prefixes, list_children, Children, DEFAULT_DELIMITER};
+pub use aws_cli_common::retry::{ErrorKind, RetryPolicy};
 
 mod error;
+mod filter;
+mod object;
+mod prefix;
+
+/// Stop prefetching pages while this many objects are still waiting to be consumed, which is the
+/// size of a full page.
+const MAX_BUFFERED_OBJECTS: usize = 1000;
+
+type ListPages = PaginationStream<StdResult<ListObjectsV2Output, SdkError<ListObjectsV2Error>>>;
+
+/// S3 client for `config`. Buckets are addressed by path rather than by host name when a custom
+/// endpoint is set, which is what MinIO and LocalStack expect.
+pub fn client(config: &SdkConfig) -> Client {
+    let mut builder = Config::from(config).to_builder();
+    if config.endpoint_url().is_some() {
+        builder = builder.force_path_style(true);
+    }
+    Client::from_conf(builder.build())
+}
 
+/// Stream of the objects in a bucket, the next page is already requested while the objects of
+/// the current one are being consumed.
 pub struct BucketKeyIterator<'a> {
     bucket: &'a str,
     prefix: Option<&'a str>,
-    cli: &'a S3Client,
+    delimiter: Option<&'a str>,
+    cli: &'a Client,
+    filter: KeyFilter,
+    retry: RetryPolicy,
+    /// Attempts made for the page being listed
+    attempt: u32,
+    /// Where the listing starts over after a failed page
     continuation_token: Option<String>,
-    keys: Vec<String>,
+    objects: VecDeque<BucketObject>,
+    /// Common prefixes listed so far when there is a delimiter
+    prefixes: Vec<String>,
+    pages: Option<ListPages>,
+    /// Set while waiting to retry a failed page
+    retry_delay: Option<Pin<Box<Sleep>>>,
     empty: bool,
 }
 
 impl<'a> BucketKeyIterator<'a> {
-    pub fn new(
-        bucket: &'a str,
-        prefix: Option<&'a str>,
-        cli: &'a S3Client,
-    ) -> BucketKeyIterator<'a> {
+    pub fn new(bucket: &'a str, prefix: Option<&'a str>, cli: &'a Client) -> BucketKeyIterator<'a> {
         BucketKeyIterator {
             bucket,
             prefix,
+            delimiter: None,
             cli,
+            filter: KeyFilter::none(),
+            retry: RetryPolicy::default(),
+            attempt: 1,
             continuation_token: None,
-            keys: vec![],
+            objects: VecDeque::new(),
+            prefixes: vec![],
+            pages: None,
+            retry_delay: None,
             empty: false,
         }
     }
 
-    pub async fn iter_next(&mut self) -> Result<Option<String>> {
-        // TODO: Use streams to implement the old iterator
-        if let Some(key) = self.keys.pop() {
-            return Ok(Some(key));
-        }
+    /// Only return keys accepted by `filter`, by default every key under the prefix is returned.
+    pub fn with_filter(mut self, filter: KeyFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Roll keys with `delimiter` past the prefix up into their common prefix, which are returned
+    /// by `take_prefixes` instead of being streamed.
+    pub fn with_delimiter(mut self, delimiter: &'a str) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
 
-        if self.empty {
-            return Ok(None);
+    /// How failed listings are retried, `RetryPolicy::default()` unless set.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub async fn iter_next(&mut self) -> Result<Option<BucketObject>> {
+        self.next().await.transpose()
+    }
+
+    /// Common prefixes listed so far with `with_delimiter`, they are all there once the stream
+    /// has ended.
+    pub fn take_prefixes(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.prefixes)
+    }
+
+    /// Pages from the last continuation token on, the paginator only requests a page when it is
+    /// polled.
+    fn list_pages(&self) -> ListPages {
+        self.cli
+            .list_objects_v2()
+            .bucket(self.bucket)
+            .set_prefix(self.prefix.map(|p| p.to_owned()))
+            .set_delimiter(self.delimiter.map(|d| d.to_owned()))
+            .set_continuation_token(self.continuation_token.clone())
+            .into_paginator()
+            .send()
+    }
+
+    fn add_page(&mut self, response: ListObjectsV2Output) -> Result<()> {
+        if let Some(objects) = response.contents {
+            let filter = &self.filter;
+            for object in objects.into_iter().filter(|object| match object.key {
+                Some(ref key) => filter.matches(key),
+                None => false,
+            }) {
+                self.objects.push_back(BucketObject::try_from(object)?);
+            }
+        }
+        for common_prefix in response.common_prefixes.unwrap_or_default() {
+            self.prefixes.extend(common_prefix.prefix);
         }
 
-        let request = rusoto_s3::ListObjectsV2Request {
-            bucket: self.bucket.to_owned(),
-            prefix: self.prefix.map(|p| p.to_owned()),
-            continuation_token: self.continuation_token.clone(),
-            ..Default::default()
+        self.continuation_token = response.next_continuation_token;
+        Ok(())
+    }
+
+    /// Drop the paginator after a failed page, it is created again from the continuation token
+    /// once `delay` has passed. Returns the error when it should not be retried.
+    fn retry_page(&mut self, error: Error) -> Option<Error> {
+        self.pages = None;
+        let delay = match self.retry.delay(error.kind(), self.attempt) {
+            Some(delay) => delay,
+            None => {
+                self.empty = true;
+                return Some(error);
+            }
         };
+        warn!(
+            "Listing s3://{}/{} failed on attempt {}/{}, retrying in {:?}: {}",
+            self.bucket,
+            self.prefix.unwrap_or_default(),
+            self.attempt,
+            self.retry.max_attempts,
+            delay,
+            error
+        );
+        self.attempt += 1;
+        self.retry_delay = Some(Box::pin(tokio::time::sleep(delay)));
+        None
+    }
+}
+
+impl<'a> Stream for BucketKeyIterator<'a> {
+    type Item = Result<BucketObject>;
 
-        let response = self
-            .cli
-            .list_objects_v2(request)
-            .await
-            .map_err(|e| eyre::eyre!(e))?;
-        if let Some(keys) = response.contents {
-            let mut output_keys = vec![];
-            for key in keys.iter().filter(|object| match object.key {
-                Some(ref key) => {
-                    key.ends_with(".gz") && self.prefix.map(|p| key.contains(p)).unwrap_or(false)
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(delay) = this.retry_delay.as_mut() {
+                if delay.as_mut().poll(cx).is_ready() {
+                    this.retry_delay = None;
                 }
-                None => false,
-            }) {
-                let key = key.key.as_ref().ok_or(Error::KeyNotPresent)?;
-                output_keys.push(key.clone());
             }
 
-            output_keys.reverse();
-            self.keys = output_keys;
-            self.continuation_token = response.next_continuation_token;
-            self.empty = !response.is_truncated.unwrap();
-            return Ok(self.keys.pop());
-        }
+            if this.retry_delay.is_none()
+                && !this.empty
+                && this.objects.len() < MAX_BUFFERED_OBJECTS
+            {
+                if this.pages.is_none() {
+                    this.pages = Some(this.list_pages());
+                }
+                let pages = this.pages.as_mut().expect("paginator was just created");
+                if let Poll::Ready(page) = pages.poll_next(cx) {
+                    match page {
+                        Some(Ok(response)) => {
+                            this.attempt = 1;
+                            this.add_page(response)?;
+                        }
+                        Some(Err(e)) => {
+                            if let Some(error) = this.retry_page(Error::from(e)) {
+                                return Poll::Ready(Some(Err(error)));
+                            }
+                        }
+                        None => {
+                            this.pages = None;
+                            this.empty = true;
+                        }
+                    }
+                    continue;
+                }
+            }
 
-        Ok(None)
+            if let Some(object) = this.objects.pop_front() {
+                return Poll::Ready(Some(Ok(object)));
+            }
+            if this.empty {
+                return Poll::Ready(None);
+            }
+            return Poll::Pending;
+        }
     }
 }
+
+/// Download a whole object. The download starts over when the request or reading the body fails
+/// with a retryable error.
+pub async fn download_object(
+    cli: &Client,
+    bucket: &str,
+    key: &str,
+    retry: &RetryPolicy,
+) -> Result<Vec<u8>> {
+    let what = format!("Downloading s3://{}/{}", bucket, key);
+    retry
+        .retry(&what, move || async move {
+            let response = cli.get_object().bucket(bucket).key(key).send().await?;
+            let data = response.body.collect().await.map_err(|e| Error::Request {
+                kind: ErrorKind::Connection,
+                source: eyre::Report::new(e),
+            })?;
+            Ok(data.to_vec())
+        })
+        .await
+}
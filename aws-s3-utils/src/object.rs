@@ -0,0 +1,76 @@
+use std::convert::TryFrom;
+
+use aws_sdk_s3::types::Object;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::error::Error;
+
+/// Metadata of an object as returned by ListObjectsV2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketObject {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub e_tag: Option<String>,
+    pub storage_class: Option<String>,
+}
+
+impl BucketObject {
+    /// Objects in these storage classes have to be restored before they can be downloaded.
+    pub fn is_archived(&self) -> bool {
+        matches!(
+            self.storage_class.as_deref(),
+            Some("GLACIER") | Some("DEEP_ARCHIVE")
+        )
+    }
+}
+
+impl TryFrom<Object> for BucketObject {
+    type Error = Error;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
+        Ok(BucketObject {
+            key: object.key.ok_or(Error::KeyNotPresent)?,
+            size: object.size.unwrap_or_default() as u64,
+            last_modified: object
+                .last_modified
+                .and_then(|date| Utc.timestamp_opt(date.secs(), date.subsec_nanos()).single()),
+            e_tag: object.e_tag,
+            storage_class: object.storage_class.map(|class| class.as_str().to_owned()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use aws_sdk_s3::primitives::DateTime;
+    use aws_sdk_s3::types::{Object, ObjectStorageClass};
+    use chrono::{TimeZone, Utc};
+
+    use super::BucketObject;
+
+    #[test]
+    fn test_from_sdk_object() {
+        let object = BucketObject::try_from(
+            Object::builder()
+                .key("logs/a.log.gz")
+                .size(1024)
+                .last_modified(DateTime::from_secs(1709288430))
+                .storage_class(ObjectStorageClass::Glacier)
+                .build(),
+        )
+        .unwrap();
+
+        assert_eq!(object.key, "logs/a.log.gz");
+        assert_eq!(object.size, 1024);
+        assert_eq!(
+            object.last_modified,
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 20, 30).unwrap())
+        );
+        assert!(object.is_archived());
+
+        assert!(BucketObject::try_from(Object::builder().build()).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use aws_sdk_s3::Client;
+
+use crate::error::Result;
+use crate::object::BucketObject;
+use crate::{BucketKeyIterator, RetryPolicy};
+
+/// Delimiter used by the S3 console and AWS services to lay out keys as folders.
+pub const DEFAULT_DELIMITER: &str = "/";
+
+/// What is directly under a prefix when listing with a delimiter.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Children {
+    /// Common prefixes of the deeper keys, each one ending with the delimiter
+    pub prefixes: Vec<String>,
+    /// Objects with no delimiter past the prefix
+    pub objects: Vec<BucketObject>,
+}
+
+/// List what is directly under `prefix`, keys with `delimiter` further down are rolled up into
+/// their common prefix. Pages are retried like in `BucketKeyIterator`, so a throttled listing
+/// does not start over.
+pub async fn list_children(
+    cli: &Client,
+    bucket: &str,
+    prefix: &str,
+    delimiter: &str,
+    retry: &RetryPolicy,
+) -> Result<Children> {
+    let mut iterator = BucketKeyIterator::new(bucket, Some(prefix), cli)
+        .with_delimiter(delimiter)
+        .with_retry(retry.clone());
+    let mut objects = vec![];
+    while let Some(object) = iterator.iter_next().await? {
+        objects.push(object);
+    }
+    Ok(Children {
+        prefixes: iterator.take_prefixes(),
+        objects,
+    })
+}
+
+/// The "folders" directly under `prefix`, e.g. the account ids under `AWSLogs/`.
+pub async fn list_child_prefixes(
+    cli: &Client,
+    bucket: &str,
+    prefix: &str,
+    retry: &RetryPolicy,
+) -> Result<Vec<String>> {
+    let children = list_children(cli, bucket, prefix, DEFAULT_DELIMITER, retry).await?;
+    Ok(children.prefixes)
+}
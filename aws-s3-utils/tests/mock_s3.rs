@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use aws_s3_utils::{
+    download_object, list_child_prefixes, list_children, BucketKeyIterator, ErrorKind, KeyFilter,
+    RetryPolicy,
+};
+use s3_mock::{Failure, MockS3};
+
+fn fast_retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(1),
+        throttling_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(10),
+    }
+}
+
+async fn list(iterator: BucketKeyIterator<'_>) -> Vec<String> {
+    iterator
+        .map(|object| object.unwrap().key)
+        .collect::<Vec<_>>()
+        .await
+}
+
+#[tokio::test]
+async fn test_list_all_pages() {
+    let s3 = MockS3::start();
+    s3.set_page_size(2);
+    for index in 0..5 {
+        s3.put_object("logs", &format!("flow/{}.log.gz", index), vec![0u8; index]);
+    }
+    s3.put_object("logs", "other/a.log.gz", "");
+    let client = s3.client();
+
+    let keys = list(BucketKeyIterator::new("logs", Some("flow/"), &client)).await;
+    assert_eq!(
+        keys,
+        (0..5)
+            .map(|index| format!("flow/{}.log.gz", index))
+            .collect::<Vec<_>>()
+    );
+
+    let tokens: Vec<_> = s3
+        .requests()
+        .into_iter()
+        .map(|request| request.query.get("continuation-token").cloned())
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![None, Some("2".to_string()), Some("4".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn test_list_metadata_and_filter() {
+    let s3 = MockS3::start();
+    s3.put_object("logs", "flow/", "");
+    s3.put_object("logs", "flow/a b.log.gz", "12345");
+    let client = s3.client();
+
+    let filter = KeyFilter::none().exclude_suffix("/");
+    let mut iterator = BucketKeyIterator::new("logs", None, &client).with_filter(filter);
+    let object = iterator.iter_next().await.unwrap().unwrap();
+    assert_eq!(object.key, "flow/a b.log.gz");
+    assert_eq!(object.size, 5);
+    assert_eq!(
+        object.last_modified.unwrap().to_rfc3339(),
+        "2024-03-01T10:00:00+00:00"
+    );
+    assert!(!object.is_archived());
+    assert!(iterator.iter_next().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_empty_and_missing_bucket() {
+    let s3 = MockS3::start();
+    s3.create_bucket("empty");
+    let client = s3.client();
+
+    assert!(list(BucketKeyIterator::new("empty", None, &client))
+        .await
+        .is_empty());
+
+    let mut missing = BucketKeyIterator::new("missing", None, &client).with_retry(fast_retry());
+    let error = missing.iter_next().await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Permanent);
+    assert_eq!(s3.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_retry_listing() {
+    let s3 = MockS3::start();
+    s3.set_page_size(1);
+    s3.put_object("logs", "a.log.gz", "");
+    s3.put_object("logs", "b.log.gz", "");
+    let client = s3.client();
+
+    s3.fail_next(&[Failure::Status(503), Failure::Status(500)]);
+    let iterator = BucketKeyIterator::new("logs", None, &client).with_retry(fast_retry());
+    assert_eq!(list(iterator).await, vec!["a.log.gz", "b.log.gz"]);
+    assert_eq!(s3.requests().len(), 4);
+
+    s3.fail_next(&[Failure::Status(503); 3]);
+    let mut iterator = BucketKeyIterator::new("logs", None, &client).with_retry(fast_retry());
+    let error = iterator.iter_next().await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Throttling);
+}
+
+#[tokio::test]
+async fn test_download_starts_over() {
+    let s3 = MockS3::start();
+    let data: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+    s3.put_object("logs", "flow/a.log.gz", data.clone());
+    let client = s3.client();
+
+    s3.fail_next(&[Failure::BrokenBody, Failure::Status(500)]);
+    let downloaded = download_object(&client, "logs", "flow/a.log.gz", &fast_retry())
+        .await
+        .unwrap();
+    assert_eq!(downloaded, data);
+    assert_eq!(s3.requests().len(), 3);
+
+    let missing = download_object(&client, "logs", "flow/missing.log.gz", &fast_retry()).await;
+    assert_eq!(missing.unwrap_err().kind(), ErrorKind::Permanent);
+}
+
+#[tokio::test]
+async fn test_list_children() {
+    let s3 = MockS3::start();
+    s3.set_page_size(2);
+    for key in &[
+        "AWSLogs/111111111111/vpcflowlogs/eu-central-1/a.log.gz",
+        "AWSLogs/111111111111/vpcflowlogs/eu-west-1/b.log.gz",
+        "AWSLogs/111111111111/elasticloadbalancing/eu-central-1/c.log.gz",
+        "AWSLogs/222222222222/vpcflowlogs/us-east-1/d.log.gz",
+        "AWSLogs/readme.txt",
+        "other.txt",
+    ] {
+        s3.put_object("logs", key, "data");
+    }
+    let client = s3.client();
+
+    let prefixes = list_child_prefixes(&client, "logs", "", &fast_retry())
+        .await
+        .unwrap();
+    assert_eq!(prefixes, vec!["AWSLogs/"]);
+
+    // One prefix per page, and a throttled page in the middle
+    s3.fail_next(&[Failure::Status(503)]);
+    let children = list_children(&client, "logs", "AWSLogs/", "/", &fast_retry())
+        .await
+        .unwrap();
+    assert_eq!(
+        children.prefixes,
+        vec!["AWSLogs/111111111111/", "AWSLogs/222222222222/"]
+    );
+    let keys: Vec<_> = children.objects.iter().map(|object| &object.key).collect();
+    assert_eq!(keys, vec!["AWSLogs/readme.txt"]);
+
+    let prefixes = list_child_prefixes(&client, "logs", "AWSLogs/111111111111/", &fast_retry())
+        .await
+        .unwrap();
+    assert_eq!(
+        prefixes,
+        vec![
+            "AWSLogs/111111111111/elasticloadbalancing/",
+            "AWSLogs/111111111111/vpcflowlogs/"
+        ]
+    );
+
+    s3.fail_next(&[Failure::Status(503); 3]);
+    let throttled = list_children(&client, "logs", "AWSLogs/", "/", &fast_retry()).await;
+    assert_eq!(throttled.unwrap_err().kind(), ErrorKind::Throttling);
+
+    let missing = list_child_prefixes(&client, "missing", "", &fast_retry()).await;
+    assert_eq!(missing.unwrap_err().kind(), ErrorKind::Permanent);
+}
@@ -1,22 +1,22 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use aws_sdk_ssm::Client as SsmClient;
 use clap::Parser;
 use color_eyre::eyre::Result;
 use envfile::EnvFile;
 use log::Level::Debug;
 use log::{debug, log_enabled};
 use regex::Regex;
-use rusoto_core::Region;
-use rusoto_ssm::{GetParametersByPathRequest, Ssm, SsmClient};
 use tokio::{fs::File, spawn};
 
+use aws_cli_common::retry::RetryPolicy;
+use aws_cli_common::AwsOptions;
+
 #[derive(Debug, Parser)]
 struct Options {
     #[arg(short = 'f', long = "env-file")]
     env_file: PathBuf,
-    #[arg(short = 'r', long)]
-    region: Option<Region>,
     #[arg(short = 'p', long)]
     path: String,
     #[arg(short = 'e', long)]
@@ -27,30 +27,23 @@ struct Options {
     uppercase: bool,
     #[arg(short = 'l', long)]
     lowercase: bool,
+    #[command(flatten)]
+    aws: AwsOptions,
 }
 
-impl Options {
-    pub fn get_region(&self) -> Region {
-        self.region.clone().unwrap_or_else(Region::default)
-    }
-}
-
-lazy_static::lazy_static! {
-    pub (crate) static ref OPTIONS: Options = Options::parse();
-}
+aws_cli_common::options!(Options);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    color_eyre::install()?;
-    env_logger::init();
+    aws_cli_common::init()?;
 
     let file = File::create(&OPTIONS.env_file).await?;
     drop(file);
 
     let mut env = spawn(async { EnvFile::new(&OPTIONS.env_file) }).await??;
 
-    let cli = SsmClient::new(OPTIONS.get_region());
-    let configs = fetch_configs(&cli).await?;
+    let cli = OPTIONS.aws.client(SsmClient::new).await;
+    let configs = fetch_configs(&cli, &OPTIONS.aws.retry_policy()).await?;
     for (key, value) in configs {
         let key = transform_key(&key, &OPTIONS);
         env.update(&key, &value);
@@ -59,30 +52,23 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn fetch_configs(cli: &SsmClient) -> Result<HashMap<String, String>> {
-    let mut output = HashMap::new();
-    let mut next_token: Option<String> = None;
-    loop {
-        let request = GetParametersByPathRequest {
-            path: OPTIONS.path.clone(),
-            with_decryption: Some(true),
-            next_token: next_token.clone(),
-            ..Default::default()
-        };
-        let response = cli.get_parameters_by_path(request).await?;
-        if let Some(parameters) = response.parameters {
-            for parameter in parameters {
-                output.insert(parameter.name.unwrap(), parameter.value.unwrap());
-            }
-        }
-
-        if response.next_token.is_some() {
-            next_token = response.next_token.clone();
-        } else {
-            break;
-        }
-    }
-    Ok(output)
+/// All the parameters under the path, the listing starts over from the first page when one fails.
+async fn fetch_configs(cli: &SsmClient, retry: &RetryPolicy) -> Result<HashMap<String, String>> {
+    let request = cli
+        .get_parameters_by_path()
+        .path(&OPTIONS.path)
+        .with_decryption(true);
+    let what = format!("Fetching parameters from {}", OPTIONS.path);
+    let pages = retry
+        .retry(&what, || {
+            request.clone().into_paginator().send().try_collect()
+        })
+        .await?;
+    Ok(pages
+        .into_iter()
+        .flat_map(|page| page.parameters.unwrap_or_default())
+        .map(|parameter| (parameter.name.unwrap(), parameter.value.unwrap()))
+        .collect())
 }
 
 fn transform_key(key: &str, options: &Options) -> String {
@@ -128,7 +114,7 @@ mod tests {
                     uppercase: true,
                     lowercase: false,
                     env_file: "/dev/null".into(),
-                    region: None,
+                    aws: Default::default(),
                     path: "".to_string(),
                 },
                 "VARIABLE",
@@ -140,7 +126,7 @@ mod tests {
                     uppercase: false,
                     lowercase: true,
                     env_file: "/dev/null".into(),
-                    region: None,
+                    aws: Default::default(),
                     path: "".to_string(),
                 },
                 "variable",
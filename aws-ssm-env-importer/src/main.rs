@@ -2,20 +2,19 @@ use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
 use std::path::PathBuf;
 use std::process;
-use std::str::FromStr;
-use std::thread;
-use std::time::Duration;
 
+use aws_sdk_ssm::types::ParameterType;
+use aws_sdk_ssm::Client as SsmClient;
 use clap::Parser as ClapParser;
 use color_eyre::eyre::{Result, WrapErr};
 use envfile::EnvFile;
 use r2d2::Pool;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
-use rusoto_core::{Region, RusotoError};
-use rusoto_ssm::{PutParameterError, PutParameterRequest, Ssm, SsmClient as RusotoSsmClient};
 use tokio::runtime::{Builder, Runtime};
 
+use aws_cli_common::AwsOptions;
+
 const MAXIMUM_SSM_CLIENTS: u32 = 4;
 
 #[derive(Debug)]
@@ -23,7 +22,7 @@ struct DumbError;
 
 impl Display for DumbError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        Ok(f.write_str("Error")?)
+        f.write_str("Error")
     }
 }
 
@@ -33,8 +32,6 @@ impl std::error::Error for DumbError {}
 struct Options {
     #[arg(short = 'f', long = "env-file")]
     env_file: PathBuf,
-    #[arg(short = 'r', long = "region")]
-    region: String,
     #[arg(short = 'e', long = "environment")]
     environment: String,
     #[arg(short = 'a', long = "app-name")]
@@ -48,29 +45,33 @@ struct Options {
     uppercase: bool,
     #[arg(short = 'd', long = "dry-run")]
     dry_run: bool,
+    #[command(flatten)]
+    aws: AwsOptions,
 }
 
-struct SsmConnectionPool(Region);
+/// Hands out clones of the same client, they share the underlying connections.
+struct SsmConnectionPool(SsmClient);
 
 impl r2d2::ManageConnection for SsmConnectionPool {
-    type Connection = RusotoSsmClient;
+    type Connection = SsmClient;
     type Error = DumbError;
 
     fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        Ok(RusotoSsmClient::new(self.0.clone()))
+        Ok(self.0.clone())
     }
 
     fn is_valid(&self, _: &mut Self::Connection) -> Result<(), Self::Error> {
         Ok(())
     }
 
-    fn has_broken(&self, mut conn: &mut Self::Connection) -> bool {
-        self.is_valid(&mut conn).is_err()
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.is_valid(conn).is_err()
     }
 }
 
+aws_cli_common::options!(Options);
+
 lazy_static::lazy_static! {
-    pub (crate) static ref OPTIONS: Options = Options::parse();
     static ref RUNTIME: Runtime = Builder::new_multi_thread().enable_all().build().unwrap();
 }
 
@@ -93,7 +94,7 @@ fn format_key(template: &str, key: &str, uppercase: bool, data: &HashMap<&str, &
 }
 
 fn main() -> Result<()> {
-    color_eyre::install()?;
+    aws_cli_common::init()?;
     let env = EnvFile::new(&OPTIONS.env_file)?;
     let key_template = to_template("key");
     if !OPTIONS.template.contains(&key_template) {
@@ -107,11 +108,13 @@ fn main() -> Result<()> {
 
     let pool = r2d2::Pool::builder()
         .max_size(MAXIMUM_SSM_CLIENTS)
-        .build(SsmConnectionPool(Region::from_str(&OPTIONS.region)?))?;
+        .build(SsmConnectionPool(
+            RUNTIME.block_on(OPTIONS.aws.client(SsmClient::new)),
+        ))?;
 
     env.store
         .par_iter()
-        .for_each(move |(key, value)| put_parameter(&data, pool.clone(), &key, value));
+        .for_each(move |(key, value)| put_parameter(&data, pool.clone(), key, value));
 
     Ok(())
 }
@@ -123,7 +126,7 @@ fn put_parameter(
     value: &str,
 ) {
     let ssm = pool.get().unwrap();
-    let normalized_key = format_key(&OPTIONS.template, &key, OPTIONS.uppercase, &data);
+    let normalized_key = format_key(&OPTIONS.template, key, OPTIONS.uppercase, data);
     let normalized_value = value.trim();
     if OPTIONS.dry_run {
         println!(
@@ -132,44 +135,36 @@ fn put_parameter(
         );
         return;
     }
-    loop {
-        let request = PutParameterRequest {
-            name: normalized_key.clone(),
-            value: normalized_value.to_string(),
-            type_: Some("SecureString".to_string()),
-            overwrite: Some(OPTIONS.overwrite),
-            ..Default::default()
-        };
-        match RUNTIME.block_on(ssm.put_parameter(request)) {
-            Ok(response) => {
-                println!(
-                    "{} set to version {}",
-                    normalized_key,
-                    response.version.unwrap()
-                );
-                break;
-            }
-            Err(RusotoError::Service(PutParameterError::ParameterAlreadyExists(_))) => {
-                println!("Ignored {} because it already exists", normalized_key);
-                break;
-            }
-            Err(RusotoError::Unknown(ref e))
-                if String::from_utf8_lossy(e.body.as_ref()).contains("ThrottlingException") =>
-            {
-                thread::sleep(Duration::from_secs(1));
-            }
-            error @ Err(_) => {
-                error
-                    .wrap_err_with(|| {
-                        format!(
-                            "Unexpected error while trying to put key = {:?} and value = {:?}",
-                            normalized_key, normalized_value
-                        )
-                    })
-                    .unwrap();
-            }
-        };
-    }
+    let request = ssm
+        .put_parameter()
+        .name(&normalized_key)
+        .value(normalized_value)
+        .r#type(ParameterType::SecureString)
+        .overwrite(OPTIONS.overwrite);
+    let what = format!("Putting {}", normalized_key);
+    let retry = OPTIONS.aws.retry_policy();
+    match RUNTIME.block_on(retry.retry(&what, || request.clone().send())) {
+        Ok(response) => {
+            println!("{} set to version {}", normalized_key, response.version);
+        }
+        Err(error)
+            if error
+                .as_service_error()
+                .is_some_and(|error| error.is_parameter_already_exists()) =>
+        {
+            println!("Ignored {} because it already exists", normalized_key);
+        }
+        error @ Err(_) => {
+            error
+                .wrap_err_with(|| {
+                    format!(
+                        "Unexpected error while trying to put key = {:?} and value = {:?}",
+                        normalized_key, normalized_value
+                    )
+                })
+                .unwrap();
+        }
+    };
 }
 
 #[cfg(test)]
@@ -2,7 +2,7 @@
 set -ex
 set -o pipefail
 
-BINS="delete-line delete-local-branches aws-flow-logs aws-ssm-env-importer aws-ssm-env-exporter"
+BINS="delete-line delete-local-branches aws-flow-logs aws-alb-logs aws-s3-ls aws-ssm-env-importer aws-ssm-env-exporter"
 
 for bin in $BINS; do
   cargo install --path="$bin" --root=installed
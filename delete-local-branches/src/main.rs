@@ -38,7 +38,7 @@ fn fetch_new_data(repository: &Repository) -> Result<()> {
     info!("Fetching repository data.");
     let child = process::Command::new("git")
         .current_dir(repository.path())
-        .args(&["fetch", "-p"])
+        .args(["fetch", "-p"])
         .output()
         .wrap_err("Error fetching repository data")?;
 
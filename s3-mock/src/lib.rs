@@ -0,0 +1,456 @@
+//! A tiny S3 stand-in for tests, an HTTP server on localhost which answers the ListObjectsV2 and
+//! GetObject calls made by the AWS SDK from objects kept in memory. Every connection handles a single
+//! request, failures and broken downloads can be injected to exercise retries.
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_s3::{Client, Config};
+
+/// Modification time of every object, as returned in listings.
+pub const LAST_MODIFIED: &str = "2024-03-01T10:00:00.000Z";
+const LAST_MODIFIED_HEADER: &str = "Fri, 01 Mar 2024 10:00:00 GMT";
+
+/// A request received by the mock, with the path and query parameters decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+}
+
+/// What the next requests should get instead of a proper answer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    /// An error response with this status, 503 is sent as SlowDown
+    Status(u16),
+    /// The next download stops halfway through the body
+    BrokenBody,
+}
+
+#[derive(Default)]
+struct State {
+    buckets: BTreeMap<String, BTreeMap<String, Vec<u8>>>,
+    page_size: usize,
+    failures: VecDeque<Failure>,
+    requests: Vec<Request>,
+}
+
+pub struct MockS3 {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockS3 {
+    /// Listen on a free local port, listings return up to 1000 keys per page like S3.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind the mock");
+        let address = listener.local_addr().expect("mock has no address");
+        let state = Arc::new(Mutex::new(State {
+            page_size: 1000,
+            ..Default::default()
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let server_state = state.clone();
+        let server_stopped = stopped.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    // The client just sees a closed connection if anything goes wrong
+                    let _ = handle_connection(stream, &state);
+                });
+            }
+        });
+
+        Self {
+            address,
+            state,
+            stopped,
+        }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// A client talking to this mock with dummy credentials. The SDK does not retry on its own,
+    /// like the clients of the tools.
+    pub fn client(&self) -> Client {
+        let config = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("mock", "mock", None, None, "mock"))
+            .endpoint_url(self.endpoint())
+            .force_path_style(true)
+            .retry_config(RetryConfig::disabled())
+            .build();
+        Client::from_conf(config)
+    }
+
+    /// Return at most `page_size` keys per listing, to go through pagination with a few objects.
+    pub fn set_page_size(&self, page_size: usize) {
+        self.lock().page_size = page_size.max(1);
+    }
+
+    pub fn create_bucket(&self, bucket: &str) {
+        self.lock().buckets.entry(bucket.to_owned()).or_default();
+    }
+
+    /// Store an object, the bucket is created if needed.
+    pub fn put_object(&self, bucket: &str, key: &str, data: impl Into<Vec<u8>>) {
+        self.lock()
+            .buckets
+            .entry(bucket.to_owned())
+            .or_default()
+            .insert(key.to_owned(), data.into());
+    }
+
+    /// Answer the next requests with these failures, one per request.
+    pub fn fail_next(&self, failures: &[Failure]) {
+        self.lock().failures.extend(failures);
+    }
+
+    /// Every request received so far, in order.
+    pub fn requests(&self) -> Vec<Request> {
+        self.lock().requests.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("mock state is poisoned")
+    }
+}
+
+impl Drop for MockS3 {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so it can see it was stopped
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    /// Only send this many bytes of the body while announcing all of them
+    cut_at: Option<usize>,
+}
+
+impl Response {
+    fn ok(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![],
+            body,
+            cut_at: None,
+        }
+    }
+
+    fn error(status: u16, code: &str) -> Self {
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message></Error>",
+            code, code
+        );
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/xml".to_owned())],
+            body: body.into_bytes(),
+            cut_at: None,
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    let request = Request {
+        method,
+        path: percent_decode(path),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => (percent_decode(name), percent_decode(value)),
+                None => (percent_decode(pair), String::new()),
+            })
+            .collect(),
+    };
+
+    let response = {
+        let mut state = state.lock().expect("mock state is poisoned");
+        state.requests.push(request.clone());
+        respond(&mut state, &request)
+    };
+    write_response(stream, response)
+}
+
+fn respond(state: &mut State, request: &Request) -> Response {
+    let path = request.path.trim_start_matches('/');
+    // The SDK lists `/bucket/`, with the same trailing slash as an empty key
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) if !key.is_empty() => (bucket, Some(key)),
+        Some((bucket, _)) => (bucket, None),
+        None => (path, None),
+    };
+
+    // A broken body waits for the next download, listings are not affected by it
+    let failure = match state.failures.front() {
+        Some(Failure::Status(_)) => state.failures.pop_front(),
+        Some(Failure::BrokenBody) if key.is_some() => state.failures.pop_front(),
+        _ => None,
+    };
+    if let Some(Failure::Status(status)) = failure {
+        let code = match status {
+            503 => "SlowDown",
+            500 => "InternalError",
+            _ => "MockFailure",
+        };
+        return Response::error(status, code);
+    }
+    let objects = match state.buckets.get(bucket) {
+        Some(objects) => objects,
+        None => return Response::error(404, "NoSuchBucket"),
+    };
+
+    match (request.method.as_str(), key) {
+        ("GET", None) if request.query.get("list-type").map(String::as_str) == Some("2") => {
+            list_objects(bucket, objects, &request.query, state.page_size)
+        }
+        ("GET", Some(key)) => match objects.get(key) {
+            Some(data) => {
+                let mut response = Response::ok(data.clone());
+                response.headers.extend(vec![
+                    ("Content-Type", "application/octet-stream".to_owned()),
+                    ("ETag", etag(data)),
+                    ("Last-Modified", LAST_MODIFIED_HEADER.to_owned()),
+                ]);
+                if failure == Some(Failure::BrokenBody) {
+                    response.cut_at = Some(data.len() / 2);
+                }
+                response
+            }
+            None => Response::error(404, "NoSuchKey"),
+        },
+        _ => Response::error(501, "NotImplemented"),
+    }
+}
+
+/// A listed key, or the common prefix standing for all the keys which share it when listing with
+/// a delimiter.
+enum Entry<'a> {
+    Object(&'a str, &'a [u8]),
+    Prefix(String),
+}
+
+/// Continuation tokens are the index of the first entry of the next page, common prefixes count
+/// as one entry like on S3.
+fn list_objects(
+    bucket: &str,
+    objects: &BTreeMap<String, Vec<u8>>,
+    query: &BTreeMap<String, String>,
+    page_size: usize,
+) -> Response {
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    let start_after = query
+        .get("start-after")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let max_keys = query
+        .get("max-keys")
+        .and_then(|max_keys| max_keys.parse().ok())
+        .unwrap_or(1000)
+        .min(page_size);
+    let start = match query.get("continuation-token") {
+        Some(token) => match token.parse() {
+            Ok(start) => start,
+            Err(_) => return Response::error(400, "InvalidArgument"),
+        },
+        None => 0,
+    };
+
+    let delimiter = query
+        .get("delimiter")
+        .map(String::as_str)
+        .filter(|delimiter| !delimiter.is_empty());
+
+    let mut entries = vec![];
+    for (key, data) in objects {
+        if !key.starts_with(prefix) || key.as_str() <= start_after {
+            continue;
+        }
+        let common_prefix = delimiter.and_then(|delimiter| {
+            key[prefix.len()..]
+                .find(delimiter)
+                .map(|index| key[..prefix.len() + index + delimiter.len()].to_owned())
+        });
+        match common_prefix {
+            // Keys are sorted, so the ones sharing a prefix come one after the other
+            Some(common_prefix) => match entries.last() {
+                Some(Entry::Prefix(last)) if *last == common_prefix => {}
+                _ => entries.push(Entry::Prefix(common_prefix)),
+            },
+            None => entries.push(Entry::Object(key, data)),
+        }
+    }
+    let end = (start + max_keys).min(entries.len());
+    let page = entries.get(start..end).unwrap_or_default();
+    let truncated = end < entries.len();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">");
+    xml.push_str(&format!(
+        "<Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+        escape(bucket),
+        escape(prefix),
+        page.len(),
+        max_keys,
+        truncated
+    ));
+    if let Some(delimiter) = delimiter {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", escape(delimiter)));
+    }
+    for entry in page {
+        match entry {
+            Entry::Object(key, data) => xml.push_str(&format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                escape(key),
+                LAST_MODIFIED,
+                escape(&etag(data)),
+                data.len()
+            )),
+            Entry::Prefix(common_prefix) => xml.push_str(&format!(
+                "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                escape(common_prefix)
+            )),
+        }
+    }
+    if let Some(token) = query.get("continuation-token") {
+        xml.push_str(&format!(
+            "<ContinuationToken>{}</ContinuationToken>",
+            escape(token)
+        ));
+    }
+    if truncated {
+        xml.push_str(&format!(
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            end
+        ));
+    }
+    xml.push_str("</ListBucketResult>");
+
+    let mut response = Response::ok(xml.into_bytes());
+    response
+        .headers
+        .push(("Content-Type", "application/xml".to_owned()));
+    response
+}
+
+fn write_response(mut stream: TcpStream, response: Response) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    let body = match response.cut_at {
+        Some(cut_at) => &response.body[..cut_at],
+        None => &response.body,
+    };
+    stream.write_all(body)?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Both)
+}
+
+/// Not a real md5, only has to be stable for the same content.
+fn etag(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, percent_decode};
+
+    #[test]
+    fn test_decode_and_escape() {
+        assert_eq!(percent_decode("/bucket/a%20b%2Fc%zz"), "/bucket/a b/c%zz");
+        assert_eq!(escape("a<b>&\"c\""), "a&lt;b&gt;&amp;&quot;c&quot;");
+    }
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
 use std::default::Default;
+use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
 use uuid::Uuid;
@@ -32,13 +33,12 @@ impl FromStr for Version {
     }
 }
 
-impl ToString for Version {
-    fn to_string(&self) -> String {
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Version::V4 => "4",
-            Version::V7 => "7",
+            Version::V4 => f.write_str("4"),
+            Version::V7 => f.write_str("7"),
         }
-        .to_string()
     }
 }
 #[derive(Debug, Clone, Parser)]